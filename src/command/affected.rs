@@ -0,0 +1,181 @@
+use crate::error::Result;
+use crate::relocate::{Location, Mode, Relocation, Relocator};
+use crate::serialize::read_tour;
+use crate::types::Index;
+use crate::vcs::VCS;
+use serde::Serialize;
+use std::path::PathBuf;
+
+use super::status::{stop_status, StopStatus};
+
+/// A stop whose line, or just its file, is changed by the commits under review.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AffectedStop {
+    tour_file: String,
+    tour_id: String,
+    stop_id: String,
+    title: String,
+    path: String,
+    line: usize,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_line: Option<usize>,
+}
+
+/// Splits `A..B` or `A...B` into its ends, defaulting either side to `HEAD` as git does, and
+/// whether the base should be the merge base of the two ends, as it is for `A...B`. A single
+/// revision stands for the changes it made on top of its first parent, so it has no base here.
+fn split_range(range: &str) -> (Option<String>, String, bool) {
+    let or_head = |rev: &str| {
+        if rev.is_empty() {
+            "HEAD".to_owned()
+        } else {
+            rev.to_owned()
+        }
+    };
+    if let Some(i) = range.find("...") {
+        return (Some(or_head(&range[..i])), or_head(&range[i + 3..]), true);
+    }
+    match range.find("..") {
+        Some(i) => (Some(or_head(&range[..i])), or_head(&range[i + 2..]), false),
+        None => (None, range.to_owned(), false),
+    }
+}
+
+pub struct Affected<V: VCS> {
+    vcs: V,
+    index: Index,
+    json: bool,
+}
+
+impl<V: VCS> Affected<V> {
+    pub fn new(vcs: V, index: Index, json: bool) -> Self {
+        Affected { vcs, index, json }
+    }
+
+    /// Reports every stop in `tour_files` that points into `repository` and whose file is changed
+    /// by `range`. Stops are first carried from their tour's commit to the start of the range;
+    /// stops that are already lost by then are not the range's doing, and are left out. A root
+    /// commit is compared with the empty tree: every stop in it is reported as `added`.
+    pub fn process(&self, repository: &str, range: &str, tour_files: &[PathBuf]) -> Result<()> {
+        let repo_root = self.index.get(repository)?;
+        let repo_path = repo_root.as_absolute_path();
+        let (base, head, symmetric) = split_range(range);
+        let head = self.vcs.resolve_commit(repo_path, &head)?;
+        let base = match base {
+            Some(base) => Some(self.vcs.resolve_commit(repo_path, &base)?),
+            None => self.vcs.first_parent(repo_path, &head)?,
+        };
+        let base = match base {
+            Some(base) if symmetric => Some(self.vcs.merge_base(repo_path, &base, &head)?),
+            base => base,
+        };
+        let changes = match &base {
+            Some(base) => Some(self.vcs.diff_with_version(repo_path, base, &head)?),
+            None => None,
+        };
+        let relocator = Relocator::new(&self.vcs, Mode::Direct);
+
+        let mut affected = vec![];
+        let mut tours = 0;
+        for tour_file in tour_files {
+            let tour = read_tour(tour_file)?;
+            let commit = match tour.repositories.get(repository) {
+                Some(commit) => commit,
+                None => continue,
+            };
+            let before = affected.len();
+            for stop in tour.stops.iter().filter(|s| s.repository == repository) {
+                let location = Location {
+                    path: stop.path.clone(),
+                    line: stop.line,
+                };
+                let location = match relocator.relocate(
+                    repo_path,
+                    location,
+                    stop.anchor.as_ref(),
+                    commit,
+                    base.as_ref().unwrap_or(&head),
+                )? {
+                    Relocation::Found(location) => location,
+                    Relocation::Deleted { .. } => continue,
+                };
+                let (status_label, new_location) = match &changes {
+                    Some(changes) => {
+                        let status = stop_status(changes, &location.path, location.line);
+                        let label = match status {
+                            StopStatus::Unchanged if changes.for_file(&location.path).is_some() => {
+                                "file-changed"
+                            }
+                            StopStatus::Unchanged => continue,
+                            _ => status.label(),
+                        };
+                        (label, status.location().cloned())
+                    }
+                    None => ("added", None),
+                };
+                affected.push(AffectedStop {
+                    tour_file: tour_file.display().to_string(),
+                    tour_id: tour.id.clone(),
+                    stop_id: stop.id.clone(),
+                    title: stop.title.clone(),
+                    path: location.path.as_git_path(),
+                    line: location.line,
+                    status: status_label,
+                    new_path: new_location.as_ref().map(|l| l.path.as_git_path()),
+                    new_line: new_location.as_ref().map(|l| l.line),
+                });
+            }
+            if affected.len() > before {
+                tours += 1;
+            }
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&affected)?);
+            return Ok(());
+        }
+        for stop in &affected {
+            println!(
+                "{:>12}: {} ({}: {}:{})",
+                stop.status, stop.title, stop.tour_file, stop.path, stop.line
+            );
+        }
+        println!(
+            "{} stop(s) in {} tour(s) affected by {}.",
+            affected.len(),
+            tours,
+            range
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_range;
+
+    #[test]
+    fn ranges_default_to_head() {
+        assert_eq!(
+            split_range("main..topic"),
+            (Some("main".to_owned()), "topic".to_owned(), false)
+        );
+        assert_eq!(
+            split_range("main.."),
+            (Some("main".to_owned()), "HEAD".to_owned(), false)
+        );
+        assert_eq!(split_range("abc123"), (None, "abc123".to_owned(), false));
+        assert_eq!(
+            split_range("main...topic"),
+            (Some("main".to_owned()), "topic".to_owned(), true)
+        );
+        assert_eq!(
+            split_range("...topic"),
+            (Some("HEAD".to_owned()), "topic".to_owned(), true)
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::diagnostic;
+use crate::error::{Error, Result};
+use crate::serialize::serialize_tour;
+use crate::types::{self, Index, Tour};
+use crate::vcs::{FileContents, VCS};
+use std::fs;
+use std::path::Path;
+
+pub struct Anchor<V: VCS> {
+    vcs: V,
+    index: Index,
+    keep_text: bool,
+}
+
+impl<V: VCS> Anchor<V> {
+    pub fn new(vcs: V, index: Index, keep_text: bool) -> Self {
+        Anchor {
+            vcs,
+            index,
+            keep_text,
+        }
+    }
+
+    pub fn process(&self, tour_path: &Path, mut tour: Tour) -> Result<()> {
+        for stop in tour.stops.iter_mut() {
+            let repo_path = self.index.get(&stop.repository)?;
+            let commit = tour
+                .repositories
+                .get(&stop.repository)
+                .ok_or_else(|| Error::NoCommitForRepository(stop.repository.clone()))?;
+            match self
+                .vcs
+                .lookup_file_contents(repo_path.as_absolute_path(), commit, &stop.path)?
+            {
+                FileContents::Text(code) => {
+                    let lines = code.lines().collect::<Vec<_>>();
+                    stop.anchor = types::Anchor::new(&lines, stop.line, self.keep_text);
+                    if stop.anchor.is_none() {
+                        diagnostic::warning(
+                            "stop-past-end-of-file",
+                            &format!(
+                                "stop '{}' points past the end of its file; not anchored.",
+                                stop.title
+                            ),
+                        );
+                    }
+                }
+                FileContents::Binary { .. } => {
+                    diagnostic::warning(
+                        "stop-in-binary-file",
+                        &format!(
+                            "stop '{}' points into a binary file; not anchored.",
+                            stop.title
+                        ),
+                    );
+                }
+            }
+        }
+        fs::write(tour_path, serialize_tour(tour)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use crate::error::{Error, Result};
+use crate::relocate::{Location, Mode, Relocation, Relocator};
+use crate::types::path::AbsolutePath;
+use crate::types::{Anchor, Index, Stop, Tour};
+use crate::vcs::{decode, Changes, FileContents, LineFate, VCS};
+use std::collections::HashMap;
+use std::fs;
+
+/// What the anchored stops are checked against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckTarget {
+    /// A branch, tag or commit, in every repository of the tour.
+    Revision(String),
+    /// The working tree, including uncommitted changes.
+    Worktree,
+}
+
+pub struct Check<V: VCS> {
+    vcs: V,
+    index: Index,
+    target: CheckTarget,
+}
+
+impl<V: VCS> Check<V> {
+    pub fn new(vcs: V, index: Index, target: CheckTarget) -> Self {
+        Check { vcs, index, target }
+    }
+
+    /// Where the stop's line is at the target, and the file's lines there. `None` if the line
+    /// was deleted on the way.
+    fn current_lines(
+        &self,
+        relocator: &Relocator<'_, V>,
+        worktree_diffs: &mut HashMap<String, Changes>,
+        repo_path: AbsolutePath<'_>,
+        stop: &Stop,
+        anchor: &Anchor,
+        commit: &str,
+    ) -> Result<Option<(Location, Option<String>)>> {
+        let revision = match &self.target {
+            CheckTarget::Revision(revision) => revision.as_str(),
+            CheckTarget::Worktree => "HEAD",
+        };
+        let location = Location {
+            path: stop.path.clone(),
+            line: stop.line,
+        };
+        let location =
+            match relocator.relocate(repo_path, location, Some(anchor), commit, revision)? {
+                Relocation::Found(location) => location,
+                Relocation::Deleted { .. } => return Ok(None),
+            };
+        if self.target != CheckTarget::Worktree {
+            let code = match self
+                .vcs
+                .lookup_file_contents(repo_path, revision, &location.path)?
+            {
+                FileContents::Text(code) => Some(code),
+                FileContents::Binary { .. } => None,
+            };
+            return Ok(Some((location, code)));
+        }
+
+        if !worktree_diffs.contains_key(&stop.repository) {
+            let head = self.vcs.resolve_commit(repo_path, "HEAD")?;
+            let changes = self.vcs.diff_with_worktree(repo_path, &head)?;
+            worktree_diffs.insert(stop.repository.clone(), changes);
+        }
+        let location =
+            match worktree_diffs[&stop.repository].map_line(&location.path, location.line) {
+                LineFate::Unchanged => location,
+                LineFate::Moved { line } => Location {
+                    path: location.path,
+                    line,
+                },
+                LineFate::Renamed { path, line } => Location { path, line },
+                LineFate::Deleted { .. } => return Ok(None),
+            };
+        let bytes = fs::read(repo_path.as_path().join(location.path.as_path_buf()))?;
+        Ok(Some((location, decode(&bytes))))
+    }
+
+    /// Relocates every anchored stop to the target, and reports those whose line there no
+    /// longer holds the anchored content, along with where the anchor can be found now.
+    pub fn process(&self, tour: &Tour) -> Result<()> {
+        let relocator = Relocator::new(&self.vcs, Mode::Direct);
+        let mut worktree_diffs = HashMap::new();
+        let mut stale = 0;
+        for stop in &tour.stops {
+            let anchor = match &stop.anchor {
+                Some(anchor) => anchor,
+                None => continue,
+            };
+            let repo_root = self.index.get(&stop.repository)?;
+            let commit = tour
+                .repositories
+                .get(&stop.repository)
+                .ok_or_else(|| Error::NoCommitForRepository(stop.repository.clone()))?;
+            let position = format!(
+                "{}:{}:{}",
+                stop.repository,
+                stop.path.as_git_path(),
+                stop.line
+            );
+            let current = self.current_lines(
+                &relocator,
+                &mut worktree_diffs,
+                repo_root.as_absolute_path(),
+                stop,
+                anchor,
+                commit,
+            )?;
+            let (location, code) = match current {
+                Some((location, Some(code))) => (location, code),
+                Some((_, None)) => continue,
+                None => {
+                    stale += 1;
+                    println!("{} ({}): line was deleted", stop.title, position);
+                    continue;
+                }
+            };
+            let lines = code.lines().collect::<Vec<_>>();
+            if anchor.matches(&lines, location.line) {
+                continue;
+            }
+            stale += 1;
+            match anchor.find(&lines, location.line) {
+                Some(line) => println!(
+                    "{} ({}): line no longer matches its anchor; anchor found at {}:{}",
+                    stop.title,
+                    position,
+                    location.path.as_git_path(),
+                    line
+                ),
+                None => println!(
+                    "{} ({}): line no longer matches its anchor; anchor not found",
+                    stop.title, position
+                ),
+            }
+        }
+        if stale > 0 {
+            return Err(Error::StaleStops(stale));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Check, CheckTarget};
+    use crate::error::Error;
+    use crate::serialize::parse_tour;
+    use crate::types::path::AbsolutePathBuf;
+    use crate::types::{Anchor, Index};
+    use crate::vcs::testing::{add_files, commit};
+    use crate::vcs::Git;
+    use git2::Repository;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn stops_are_checked_against_the_target() {
+        let dir = TempDir::new("repo").unwrap().into_path();
+        let repo = Repository::init(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "1\n2\ntarget\n4\n5\n").unwrap();
+        let first = commit(&repo, add_files(&repo, vec!["a.txt"]).unwrap(), "first").unwrap();
+        fs::write(dir.join("a.txt"), "0\n1\n2\ntarget\n4\n5\n").unwrap();
+        commit(&repo, add_files(&repo, vec!["a.txt"]).unwrap(), "second").unwrap();
+
+        let mut tour = parse_tour(&format!(
+            r#"{{
+                "protocolVersion": "1.0", "id": "t", "title": "T", "description": "",
+                "stops": [{{ "id": "1", "title": "A", "body": "", "relPath": "a.txt",
+                             "repository": "r", "line": 3, "childStops": [] }}],
+                "repositories": [{{ "repository": "r", "commit": "{}" }}]
+            }}"#,
+            first
+        ))
+        .unwrap();
+        tour.stops[0].anchor = Anchor::new(&["1", "2", "target", "4", "5"], 3, false);
+        let mut entries = HashMap::new();
+        entries.insert("r".to_owned(), AbsolutePathBuf::new(dir.clone()).unwrap());
+        let index = Index::new(entries, vec![]);
+        let check = |target| Check::new(Git::new(), index.clone(), target).process(&tour);
+
+        assert!(check(CheckTarget::Revision("HEAD".to_owned())).is_ok());
+        assert!(check(CheckTarget::Worktree).is_ok());
+        fs::write(dir.join("a.txt"), "0\n1\n2\nrewritten\n4\n5\n").unwrap();
+        assert!(check(CheckTarget::Revision("HEAD".to_owned())).is_ok());
+        match check(CheckTarget::Worktree) {
+            Err(Error::StaleStops(1)) => {}
+            other => panic!("expected a stale stop, got {:?}", other.err()),
+        }
+    }
+}
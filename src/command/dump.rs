@@ -1,6 +1,52 @@
+use crate::diagnostic;
 use crate::error::{Error, Result};
+use crate::links::{document_stem, stop_anchor, Catalog};
+use crate::types::path::{AbsolutePathBuf, RelativePathBuf};
 use crate::types::{Index, Stop, Tour};
-use crate::vcs::VCS;
+use crate::vcs::{expand_tabs, FileContents, VCS};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// What `tourist dump` writes a tour as.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpFormat {
+    Markdown,
+    /// One JSON object holding the tour and its stops, with links resolved as in Markdown.
+    Json,
+}
+
+impl DumpFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            DumpFormat::Markdown => "md",
+            DumpFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(DumpFormat::Markdown),
+            "json" => Ok(DumpFormat::Json),
+            _ => Err(format!("unknown dump format '{}'", s)),
+        }
+    }
+}
+
+/// What is shown under a stop's body.
+enum Context {
+    None,
+    Code(String),
+    Note(String),
+}
 
 pub enum Dump<V: VCS> {
     Context {
@@ -8,8 +54,43 @@ pub enum Dump<V: VCS> {
         index: Index,
         above: usize,
         below: usize,
+        tab_width: Option<usize>,
+        keep_going: bool,
+    },
+    NoContext {
+        keep_going: bool,
     },
-    NoContext,
+}
+
+/// The files `tour`'s stops point at, as (repository, commit, path), skipping stops that don't
+/// resolve.
+pub fn files(tour: &Tour, index: &Index) -> Vec<(AbsolutePathBuf, String, RelativePathBuf)> {
+    tour.stops
+        .iter()
+        .filter_map(|stop| {
+            let repo_path = index.get(&stop.repository).ok()?;
+            let commit = tour.repositories.get(&stop.repository)?;
+            Some((repo_path, commit.clone(), stop.path.clone()))
+        })
+        .collect()
+}
+
+fn heading(stop: &Stop) -> String {
+    format!(
+        "<a id=\"{}\"></a>\n## {}\n*{}*",
+        stop_anchor(&stop.id),
+        stop.title,
+        position(stop)
+    )
+}
+
+fn position(stop: &Stop) -> String {
+    format!(
+        "{}:{} - line {}",
+        stop.repository,
+        stop.path.as_path_buf().display(),
+        stop.line
+    )
 }
 
 fn code_range(code: String, target: usize, above: usize, below: usize) -> String {
@@ -31,90 +112,213 @@ fn code_range(code: String, target: usize, above: usize, below: usize) -> String
 }
 
 impl<V: VCS> Dump<V> {
-    pub fn new() -> Self {
-        Dump::NoContext
+    pub fn new(keep_going: bool) -> Self {
+        Dump::NoContext { keep_going }
     }
 
-    pub fn with_context(vcs: V, index: Index, above: usize, below: usize) -> Self {
+    pub fn with_context(
+        vcs: V,
+        index: Index,
+        above: usize,
+        below: usize,
+        tab_width: Option<usize>,
+        keep_going: bool,
+    ) -> Self {
         Dump::Context {
             vcs,
             index,
             above,
             below,
+            tab_width,
+            keep_going,
         }
     }
 
-    fn extract_context(&self, stop: &Stop, commit: &str) -> Result<String> {
+    fn keep_going(&self) -> bool {
+        match self {
+            Dump::Context { keep_going, .. } | Dump::NoContext { keep_going } => *keep_going,
+        }
+    }
+
+    /// Lets the backend read every stop's file up front, in parallel if it can.
+    fn prefetch(&self, tour: &Tour) {
+        if let Dump::Context { vcs, index, .. } = self {
+            vcs.prefetch_files(&files(tour, index));
+        }
+    }
+
+    fn extract_context(&self, stop: &Stop, commit: &str) -> Result<Context> {
         match self {
             Dump::Context {
                 vcs,
                 index,
                 above,
                 below,
+                tab_width,
+                ..
             } => {
-                let repo_path = index
-                    .get(&stop.repository)
-                    .ok_or_else(|| Error::NotInIndex(stop.repository.clone()))?;
-
-                let content = code_range(
-                    vcs.lookup_file_contents(repo_path.as_absolute_path(), commit, &stop.path)?,
-                    stop.line,
-                    *above,
-                    *below,
-                );
-                Ok(format!("\n\n```\n{}\n```", content))
+                let repo_path = index.get(&stop.repository)?;
+
+                let contents = match vcs.lookup_file_contents(
+                    repo_path.as_absolute_path(),
+                    commit,
+                    &stop.path,
+                ) {
+                    Err(Error::LfsObjectMissing(oid)) => {
+                        return Ok(Context::Note(format!(
+                            "Git LFS object {} is not available.",
+                            oid
+                        )))
+                    }
+                    contents => contents?,
+                };
+                match contents {
+                    FileContents::Text(code) => {
+                        let code = match tab_width {
+                            Some(width) => expand_tabs(&code, *width),
+                            None => code,
+                        };
+                        Ok(Context::Code(code_range(code, stop.line, *above, *below)))
+                    }
+                    FileContents::Binary { size } => Ok(Context::Note(format!(
+                        "Binary file ({} bytes) not shown.",
+                        size
+                    ))),
+                }
             }
-            Dump::NoContext => Ok("".to_owned()),
+            Dump::NoContext { .. } => Ok(Context::None),
         }
     }
 
-    fn process_stop(&self, stop: &Stop, commit: &str) -> Result<String> {
-        let position = format!(
-            "{}:{} - line {}",
-            stop.repository,
-            stop.path.as_path_buf().display(),
-            stop.line
-        );
-        Ok(format!(
-            "## {}\n*{}*\n\n{}{}",
-            stop.title,
-            position,
-            stop.description,
-            self.extract_context(stop, commit)?
-        ))
+    fn process_stop(&self, tour: &Tour, stop: &Stop) -> Result<Context> {
+        let commit = tour
+            .repositories
+            .get(&stop.repository)
+            .ok_or_else(|| Error::NoCommitForRepository(stop.repository.to_owned()))
+            .map_err(|e| e.in_stop(tour, stop))?;
+        self.extract_context(stop, commit)
+            .map_err(|e| e.in_stop(tour, stop))
     }
 
-    pub fn process(&self, tour: &Tour) -> Result<()> {
-        let repos = tour
-            .repositories
-            .iter()
-            .map(|(r, c)| format!("{} is on commit {}", r, c))
-            .collect::<Vec<_>>()
-            .join("\n");
-        let stops = tour
-            .stops
-            .iter()
-            .map(|stop| {
-                let commit = tour
-                    .repositories
-                    .get(&stop.repository)
-                    .ok_or_else(|| Error::NoCommitForRepository(stop.repository.to_owned()))?;
-                self.process_stop(&stop, &commit)
-            })
-            .collect::<Result<Vec<_>>>()?
-            .join("\n\n-----\n\n");
-        let md = format!(
-            "# {}\n\n{}\n\n# Stops\n\n{}\n\n# Repositories\n\n{}",
-            tour.title, tour.description, stops, repos
-        );
-        println!("{}", md);
+    /// Prints `tour` in `format`, with its stop links pointing at the stops' anchors, or writes
+    /// it to `<tour-id>.md` (or `.json`) in `out_dir`, the document links from other tours point
+    /// at. Links to other tours are resolved through `catalog`.
+    pub fn process(
+        &self,
+        tour: &Tour,
+        catalog: &Catalog,
+        format: DumpFormat,
+        out_dir: Option<&Path>,
+    ) -> Result<()> {
+        let resolve = |body: &str, context: &str| {
+            let (body, unresolved) = catalog.rewrite(tour, body);
+            for message in unresolved {
+                diagnostic::warning("unresolved-link", &format!("{}: {}", context, message));
+            }
+            body
+        };
+        let description = resolve(&tour.description, &format!("Tour '{}'", tour.id));
+        self.prefetch(tour);
+        let mut errors = vec![];
+        let mut stops = vec![];
+        for stop in &tour.stops {
+            let body = resolve(
+                &stop.description,
+                &format!("Stop '{}' ({})", stop.title, stop.id),
+            );
+            match self.process_stop(tour, stop) {
+                Ok(context) => stops.push((stop, body, context)),
+                Err(e) if self.keep_going() => {
+                    let note = "This stop could not be rendered.".to_owned();
+                    stops.push((stop, body, Context::Note(note)));
+                    errors.push(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let document = match format {
+            DumpFormat::Markdown => markdown(tour, &description, &stops),
+            DumpFormat::Json => json(tour, &description, &stops),
+        };
+        match out_dir {
+            Some(dir) => {
+                let name = format!("{}.{}", document_stem(&tour.id), format.extension());
+                fs::write(dir.join(name), format!("{}\n", document))?
+            }
+            None => println!("{}", document),
+        }
+        if !errors.is_empty() {
+            return Err(Error::Multiple(errors));
+        }
         Ok(())
     }
 }
 
+fn markdown(tour: &Tour, description: &str, stops: &[(&Stop, String, Context)]) -> String {
+    let stops = stops
+        .iter()
+        .map(|(stop, body, context)| {
+            let context = match context {
+                Context::None => "".to_owned(),
+                Context::Code(code) => format!("\n\n```\n{}\n```", code),
+                Context::Note(note) => format!("\n\n*{}*", note),
+            };
+            format!("{}\n\n{}{}", heading(stop), body, context)
+        })
+        .collect::<Vec<_>>();
+    let repos = tour
+        .repositories
+        .iter()
+        .map(|(r, c)| format!("{} is on commit {}", r, c))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "# {}\n\n{}\n\n# Stops\n\n{}\n\n# Repositories\n\n{}",
+        tour.title,
+        description,
+        stops.join("\n\n-----\n\n"),
+        repos
+    )
+}
+
+fn json(tour: &Tour, description: &str, stops: &[(&Stop, String, Context)]) -> String {
+    let stops = stops
+        .iter()
+        .map(|(stop, body, context)| {
+            let mut value = json!({
+                "id": stop.id,
+                "anchor": stop_anchor(&stop.id),
+                "title": stop.title,
+                "repository": stop.repository,
+                "path": stop.path.as_git_path(),
+                "line": stop.line,
+                "body": body,
+            });
+            match context {
+                Context::None => {}
+                Context::Code(code) => value["code"] = json!(code),
+                Context::Note(note) => value["note"] = json!(note),
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+    let value = json!({
+        "id": tour.id,
+        "title": tour.title,
+        "description": description,
+        "stops": stops,
+        "repositories": tour.repositories.iter().collect::<BTreeMap<_, _>>(),
+    });
+    format!("{:#}", value)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::code_range;
+    use super::{code_range, Dump, DumpFormat};
+    use crate::error::Error;
+    use crate::links::Catalog;
+    use crate::serialize::parse_tour;
+    use crate::vcs::Git;
 
     #[test]
     fn extract_context_works() {
@@ -133,4 +337,44 @@ mod tests {
             code_range("1\n2\n3".to_owned(), 2, 10, 6)
         );
     }
+
+    #[test]
+    fn keep_going_reports_every_failed_stop() {
+        let tour = parse_tour(
+            r#"{
+                "protocolVersion": "1.0", "id": "tour", "title": "Tour", "description": "",
+                "stops": [
+                    { "id": "1", "title": "A", "body": "", "relPath": "a.rs",
+                      "repository": "missing", "line": 1, "childStops": [] },
+                    { "id": "2", "title": "B", "body": "", "relPath": "b.rs",
+                      "repository": "repo", "line": 2, "childStops": [] },
+                    { "id": "3", "title": "C", "body": "", "relPath": "c.rs",
+                      "repository": "missing", "line": 3, "childStops": [] }
+                ],
+                "repositories": [{ "repository": "repo", "commit": "abc" }]
+            }"#,
+        )
+        .unwrap();
+
+        match Dump::<Git>::new(false).process(
+            &tour,
+            &Catalog::default(),
+            DumpFormat::Markdown,
+            None,
+        ) {
+            Err(Error::InStop(context, _)) => assert_eq!(context.stop_id, "1"),
+            _ => panic!("expected the first stop to fail"),
+        }
+        match Dump::<Git>::new(true).process(&tour, &Catalog::default(), DumpFormat::Json, None) {
+            Err(Error::Multiple(errors)) => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(
+                    errors[1].to_string(),
+                    "In stop 'C' (3) of tour 'tour', at missing:c.rs:3: Could not find commit \
+                     for repository 'missing'."
+                );
+            }
+            _ => panic!("expected both failures to be reported"),
+        }
+    }
 }
@@ -0,0 +1,328 @@
+use crate::diagnostic;
+use crate::error::{Error, Result};
+use crate::relocate::{Location, Mode, Relocation, Relocator};
+use crate::serialize::read_tour;
+use crate::types::path::AbsolutePath;
+use crate::types::{Index, Tour};
+use crate::vcs::VCS;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::refresh::Refresh;
+use super::status::{stop_status, StopStatus};
+
+/// Marks hook scripts written by `tourist hook install`, so they can be safely overwritten.
+const HOOK_MARKER: &str = "# Installed by tourist.";
+
+/// The tours tracked in `repo_path`'s index that have stops in `repository`. Tracked tours that
+/// have been deleted from the working tree are skipped.
+fn tours_for<V: VCS>(
+    vcs: &V,
+    repo_path: AbsolutePath<'_>,
+    repository: &str,
+) -> Result<Vec<(PathBuf, Tour)>> {
+    let mut paths = vcs
+        .tracked_files(repo_path)?
+        .into_iter()
+        .map(|path| repo_path.as_path().join(path.as_path_buf()))
+        .filter(|path| path.extension().map_or(false, |e| e == "tour") && path.is_file())
+        .collect::<Vec<_>>();
+    paths.sort();
+    let mut tours = vec![];
+    for path in paths {
+        let tour = read_tour(&path)?;
+        if tour.repositories.contains_key(repository) {
+            tours.push((path, tour));
+        }
+    }
+    Ok(tours)
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn write_hook(hooks_dir: &Path, name: &str, command: &str) -> Result<()> {
+    let path = hooks_dir.join(name);
+    if let Ok(existing) = fs::read_to_string(&path) {
+        if !existing.contains(HOOK_MARKER) {
+            return Err(Error::HookExists(path));
+        }
+    }
+    fs::create_dir_all(hooks_dir)?;
+    fs::write(
+        &path,
+        format!("#!/bin/sh\n{}\nexec {}\n", HOOK_MARKER, command),
+    )?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
+    println!("Installed {}", path.display());
+    Ok(())
+}
+
+pub struct HookInstall<V: VCS> {
+    vcs: V,
+    index: Index,
+}
+
+impl<V: VCS> HookInstall<V> {
+    pub fn new(vcs: V, index: Index) -> Self {
+        HookInstall { vcs, index }
+    }
+
+    /// Writes a pre-commit hook that checks staged changes against the repository's tours, and,
+    /// if `refresh` is set, a post-commit hook that refreshes them to the new commit.
+    pub fn process(&self, repository: &str, block: bool, refresh: bool) -> Result<()> {
+        let repo_path = self.index.get(repository)?;
+        let hooks_dir = self.vcs.hooks_dir(repo_path.as_absolute_path())?;
+        let block = if block { " --block" } else { "" };
+        write_hook(
+            &hooks_dir,
+            "pre-commit",
+            &format!(
+                "tourist hook pre-commit{} {}",
+                block,
+                shell_quote(repository)
+            ),
+        )?;
+        if refresh {
+            write_hook(
+                &hooks_dir,
+                "post-commit",
+                &format!("tourist hook post-commit {}", shell_quote(repository)),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct PreCommit<V: VCS> {
+    vcs: V,
+    index: Index,
+    block: bool,
+}
+
+impl<V: VCS> PreCommit<V> {
+    pub fn new(vcs: V, index: Index, block: bool) -> Self {
+        PreCommit { vcs, index, block }
+    }
+
+    /// Warns about every stop in the repository's tours whose line is deleted by the changes
+    /// about to be committed. With `block`, the commit is refused; without it, a check that
+    /// cannot be made is only warned about too.
+    pub fn process(&self, repository: &str) -> Result<()> {
+        match self.check(repository) {
+            Ok(broken) if broken > 0 && self.block => Err(Error::BrokenStops(broken)),
+            Ok(_) => Ok(()),
+            Err(e) if self.block => Err(e),
+            Err(e) => {
+                diagnostic::warning(
+                    "pre-commit-failed",
+                    &format!("could not check the changes to be committed: {}", e),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Warns about the stops whose lines the staged changes delete, and counts them.
+    fn check(&self, repository: &str) -> Result<usize> {
+        let repo_root = self.index.get(repository)?;
+        let repo_path = repo_root.as_absolute_path();
+        let head = self.vcs.resolve_commit(repo_path, "HEAD")?;
+        let changes = self.vcs.diff_with_index(repo_path, &head)?;
+        let relocator = Relocator::new(&self.vcs, Mode::Direct);
+
+        let mut broken = 0;
+        for (path, tour) in tours_for(&self.vcs, repo_path, repository)? {
+            let commit = &tour.repositories[repository];
+            for stop in tour.stops.iter().filter(|s| s.repository == repository) {
+                let location = Location {
+                    path: stop.path.clone(),
+                    line: stop.line,
+                };
+                let location = match relocator.relocate(
+                    repo_path,
+                    location,
+                    stop.anchor.as_ref(),
+                    commit,
+                    &head,
+                )? {
+                    Relocation::Found(location) => location,
+                    Relocation::Deleted { .. } => continue,
+                };
+                if stop_status(&changes, &location.path, location.line) == StopStatus::Deleted {
+                    broken += 1;
+                    diagnostic::warning(
+                        "stop-line-deleted",
+                        &format!(
+                            "this commit deletes {}:{}, which '{}' in {} points at.",
+                            location.path.as_git_path(),
+                            location.line,
+                            stop.title,
+                            path.display()
+                        ),
+                    );
+                }
+            }
+        }
+        Ok(broken)
+    }
+}
+
+pub struct PostCommit<V: VCS> {
+    refresh: Refresh<V>,
+    index: Index,
+}
+
+impl<V: VCS> PostCommit<V> {
+    pub fn new(vcs: V, index: Index) -> Self {
+        PostCommit {
+            refresh: Refresh::new(vcs, index.clone(), Mode::Direct),
+            index,
+        }
+    }
+
+    /// Refreshes the repository's stops in its tours to the commit just made, leaving the tours'
+    /// other repositories where they were. A tour that cannot be refreshed is left as it was; the
+    /// commit has already happened, so there is nothing to block.
+    pub fn process(&self, repository: &str) -> Result<()> {
+        let repo_path = self.index.get(repository)?;
+        let tours = tours_for(self.refresh.vcs(), repo_path.as_absolute_path(), repository)?;
+        for (path, tour) in tours {
+            match self
+                .refresh
+                .process_repository(&path, tour, repository, "HEAD")
+            {
+                Ok(()) => println!("Refreshed {}", path.display()),
+                Err(e) => diagnostic::warning(
+                    "refresh-failed",
+                    &format!("could not refresh {}: {}", path.display(), e),
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shell_quote, PostCommit, PreCommit};
+    use crate::error::Error;
+    use crate::serialize::read_tour;
+    use crate::types::path::AbsolutePathBuf;
+    use crate::types::Index;
+    use crate::vcs::testing::{add_files, commit};
+    use crate::vcs::Git;
+    use git2::Repository;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    /// A repository `r` holding `a.txt` and a tour with stops on its lines 2 and 4, and a second
+    /// repository `other` the tour also has a stop in.
+    fn setup() -> (Index, PathBuf, String) {
+        let dir = TempDir::new("repo").unwrap().into_path();
+        let repo = Repository::init(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "1\n2\n3\n4\n5\n").unwrap();
+        let first = commit(&repo, add_files(&repo, vec!["a.txt"]).unwrap(), "first").unwrap();
+
+        let other_dir = TempDir::new("other").unwrap().into_path();
+        let other = Repository::init(&other_dir).unwrap();
+        fs::write(other_dir.join("a.txt"), "x\n").unwrap();
+        let other_commit = commit(&other, add_files(&other, vec!["a.txt"]).unwrap(), "first")
+            .unwrap()
+            .to_string();
+
+        let stop = |id: &str, repository: &str, line: usize| {
+            format!(
+                r#"{{ "id": "{}", "title": "{}", "body": "", "relPath": "a.txt",
+                      "repository": "{}", "line": {}, "childStops": [] }}"#,
+                id, id, repository, line
+            )
+        };
+        fs::write(
+            dir.join("t.tour"),
+            format!(
+                r#"{{
+                    "protocolVersion": "1.0", "id": "t", "title": "T", "description": "",
+                    "stops": [{}, {}, {}],
+                    "repositories": [
+                        {{ "repository": "r", "commit": "{}" }},
+                        {{ "repository": "other", "commit": "{}" }}
+                    ]
+                }}"#,
+                stop("2", "r", 2),
+                stop("4", "r", 4),
+                stop("x", "other", 1),
+                first,
+                other_commit
+            ),
+        )
+        .unwrap();
+        add_files(&repo, vec!["t.tour"]).unwrap();
+        fs::write(dir.join("stray.tour"), "not a tour").unwrap();
+
+        let mut entries = HashMap::new();
+        entries.insert("r".to_owned(), AbsolutePathBuf::new(dir.clone()).unwrap());
+        entries.insert("other".to_owned(), AbsolutePathBuf::new(other_dir).unwrap());
+        (Index::new(entries, vec![]), dir, other_commit)
+    }
+
+    #[test]
+    fn pre_commit_only_sees_staged_changes() {
+        let (index, dir, _) = setup();
+        let repo = Repository::open(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "1\n3\n4\n5\n").unwrap();
+        add_files(&repo, vec!["a.txt"]).unwrap();
+        fs::write(dir.join("a.txt"), "1\n3\n5\n").unwrap();
+
+        match PreCommit::new(Git::new(), index, true).process("r") {
+            Err(Error::BrokenStops(1)) => {}
+            other => panic!("expected one broken stop, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn pre_commit_only_blocks_when_asked() {
+        let (index, dir, _) = setup();
+        let repo = Repository::open(&dir).unwrap();
+        fs::write(dir.join("bad.tour"), "not a tour").unwrap();
+        add_files(&repo, vec!["bad.tour"]).unwrap();
+
+        PreCommit::new(Git::new(), index.clone(), false)
+            .process("r")
+            .unwrap();
+        match PreCommit::new(Git::new(), index, true).process("r") {
+            Err(Error::InvalidTour(..)) => {}
+            other => panic!("expected the bad tour to block, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn post_commit_only_refreshes_its_repository() {
+        let (index, dir, other_commit) = setup();
+        let repo = Repository::open(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "0\n1\n2\n3\n4\n5\n").unwrap();
+        let head = commit(&repo, add_files(&repo, vec!["a.txt"]).unwrap(), "second")
+            .unwrap()
+            .to_string();
+
+        PostCommit::new(Git::new(), index).process("r").unwrap();
+        let tour = read_tour(&dir.join("t.tour")).unwrap();
+        assert_eq!(tour.repositories["r"], head);
+        assert_eq!(tour.repositories["other"], other_commit);
+        let lines = tour.stops.iter().map(|s| s.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 5, 1]);
+    }
+
+    #[test]
+    fn quotes_repository_names() {
+        assert_eq!(shell_quote("repo"), "'repo'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
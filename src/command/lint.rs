@@ -0,0 +1,52 @@
+use crate::diagnostic;
+use crate::error::{Error, Result};
+use crate::links::Catalog;
+use crate::lint::{lint, Level, Settings};
+use crate::serialize::read_tour;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub struct Lint {
+    settings: Settings,
+    deny_warnings: bool,
+}
+
+impl Lint {
+    pub fn new(settings: Settings, deny_warnings: bool) -> Self {
+        Lint {
+            settings,
+            deny_warnings,
+        }
+    }
+
+    /// Reports every finding in each tour file, and fails if any is an error.
+    pub fn process(&self, tour_files: &[PathBuf]) -> Result<()> {
+        for rule in self.settings.unknown_rules() {
+            diagnostic::warning(
+                "unknown-lint-rule",
+                &format!("There is no lint rule named {}.", rule),
+            );
+        }
+        let mut failed = 0;
+        let mut catalogs = HashMap::new();
+        for tour_file in tour_files {
+            let tour = read_tour(tour_file)?;
+            let dir = tour_file.parent().unwrap_or_else(|| Path::new(""));
+            let catalog = match catalogs.entry(dir) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(Catalog::siblings(tour_file)?),
+            };
+            for finding in lint(&tour, &self.settings, catalog) {
+                diagnostic::finding(tour_file, &finding);
+                if finding.level == Level::Error || self.deny_warnings {
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(Error::LintFailed(failed));
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,31 @@
+mod affected;
+mod anchor;
+mod check;
 mod dump;
+mod hook;
+mod lint;
+mod open;
 mod package;
+mod pin;
+mod recover;
+mod refresh;
+mod refs;
+mod status;
+mod validate;
+mod workspace;
 
-pub use dump::Dump;
+pub use affected::Affected;
+pub use anchor::Anchor;
+pub use check::{Check, CheckTarget};
+pub use dump::{Dump, DumpFormat};
+pub use hook::{HookInstall, PostCommit, PreCommit};
+pub use lint::Lint;
+pub use open::Open;
 pub use package::Package;
+pub use pin::Pin;
+pub use recover::Recover;
+pub use refresh::Refresh;
+pub use refs::{PinRefs, UnpinRefs};
+pub use status::{Status, Target};
+pub use validate::Validate;
+pub use workspace::WorkspaceSync;
@@ -0,0 +1,77 @@
+use crate::error::{Error, Result};
+use crate::types::{Index, Tour};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// The editor command used when none is configured: `$VISUAL` or `$EDITOR`, told which line to
+/// go to with `+<line>`, as most terminal editors accept.
+fn default_command() -> Option<String> {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .map(|editor| format!("{} +{{line}} {{path}}", editor))
+}
+
+/// The words of `command` with `{path}` and `{line}` filled in. The command is split on
+/// whitespace before they are, so a path with spaces in it stays one argument.
+fn editor_args(command: &str, path: &Path, line: usize) -> Vec<String> {
+    command
+        .split_whitespace()
+        .map(|word| {
+            word.replace("{path}", &path.to_string_lossy())
+                .replace("{line}", &line.to_string())
+        })
+        .collect()
+}
+
+pub struct Open {
+    index: Index,
+    command: Option<String>,
+}
+
+impl Open {
+    pub fn new(index: Index, command: Option<String>) -> Self {
+        Open { index, command }
+    }
+
+    /// Opens the file the stop `stop_id` points at in the editor, at the stop's line. The file
+    /// is opened as it is in the working tree, which may have moved on from the tour's commit.
+    pub fn process(&self, tour: &Tour, stop_id: &str) -> Result<()> {
+        let stop = tour
+            .stops
+            .iter()
+            .find(|stop| stop.id == stop_id)
+            .ok_or_else(|| Error::UnknownStop(stop_id.to_owned()))?;
+        let repo_path = self.index.get(&stop.repository)?;
+        let path = repo_path
+            .as_absolute_path()
+            .as_path()
+            .join(stop.path.as_path_buf());
+        let command = self
+            .command
+            .clone()
+            .or_else(default_command)
+            .ok_or(Error::NoEditor)?;
+        let args = editor_args(&command, &path, stop.line);
+        let (program, args) = args.split_first().ok_or(Error::NoEditor)?;
+        if !Command::new(program).args(args).status()?.success() {
+            return Err(Error::EditorFailed(command));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::editor_args;
+    use std::path::Path;
+
+    #[test]
+    fn fills_in_path_and_line() {
+        assert_eq!(
+            editor_args("code -g {path}:{line}", Path::new("/src/my repo/a.rs"), 12),
+            vec!["code", "-g", "/src/my repo/a.rs:12"]
+        );
+    }
+}
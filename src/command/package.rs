@@ -1,47 +1,104 @@
+use crate::diagnostic;
 use crate::error::{Error, Result};
-use crate::types::{Index, Tour};
+use crate::links::Catalog;
+use crate::serialize::serialize_tour;
+use crate::types::{Index, Stop, Tour};
 use crate::vcs::VCS;
+
+use super::dump;
 use std::collections::HashSet;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use zip;
 
+/// Rewrites the stop links in `tour` as `catalog` resolves them, and reports the ones it cannot
+/// resolve. Returns whether anything was rewritten.
+fn resolve_links(tour: &mut Tour, catalog: &Catalog) -> bool {
+    let mut rewritten = false;
+    let mut resolve = |tour: &Tour, body: &str, context: &str| {
+        let (resolved, unresolved) = catalog.rewrite(tour, body);
+        for message in unresolved {
+            diagnostic::warning("unresolved-link", &format!("{}: {}", context, message));
+        }
+        rewritten |= resolved != body;
+        resolved
+    };
+    tour.description = resolve(tour, &tour.description, &format!("Tour '{}'", tour.id));
+    for i in 0..tour.stops.len() {
+        let stop = &tour.stops[i];
+        let context = format!("Stop '{}' ({})", stop.title, stop.id);
+        tour.stops[i].description = resolve(tour, &stop.description, &context);
+    }
+    rewritten
+}
+
 pub struct Package<V: VCS> {
     vcs: V,
     index: Index,
+    keep_going: bool,
 }
 
 impl<V: VCS> Package<V> {
-    pub fn new(vcs: V, index: Index) -> Self {
-        Package { vcs, index }
+    pub fn new(vcs: V, index: Index, keep_going: bool) -> Self {
+        Package {
+            vcs,
+            index,
+            keep_going,
+        }
+    }
+
+    fn lookup(&self, tour: &Tour, stop: &Stop) -> Result<Vec<u8>> {
+        self.vcs.lookup_file_bytes(
+            self.index.get(&stop.repository)?.as_absolute_path(),
+            tour.repositories
+                .get(&stop.repository)
+                .ok_or_else(|| Error::NoCommitForRepository(stop.repository.clone()))?,
+            &stop.path,
+        )
     }
 
-    pub fn process(&self, zip_path: &Path, tour: Tour, tour_source: &str) -> Result<()> {
+    /// Writes the tour and the files its stops point at to `zip_path`. Stop links are rewritten
+    /// as `dump` does, except that links to other tours point at their files, which are bundled
+    /// as `tours/<tour-id>.tour`; links that `catalog` cannot resolve are reported and kept.
+    pub fn process(
+        &self,
+        zip_path: &Path,
+        mut tour: Tour,
+        tour_source: &str,
+        catalog: Catalog,
+    ) -> Result<()> {
+        let catalog = catalog.packaged();
+        let linked = catalog
+            .linked_tours(&tour)
+            .into_iter()
+            .map(|(id, path)| (catalog.document(id), path.to_owned()))
+            .collect::<Vec<_>>();
+        let rewritten = resolve_links(&mut tour, &catalog);
+
         let f = File::create(zip_path)?;
         let mut zip = zip::ZipWriter::new(f);
         let options =
             zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
 
+        self.vcs.prefetch_files(&dump::files(&tour, &self.index));
         let mut files = HashSet::new();
-        for stop in tour.stops {
-            files.insert((stop.repository, stop.path));
-        }
-
-        for (repository, path) in files {
-            let content = self.vcs.lookup_file_bytes(
-                self.index
-                    .get(&repository)
-                    .ok_or_else(|| Error::NotInIndex(repository.clone()))?
-                    .as_absolute_path(),
-                tour.repositories
-                    .get(&repository)
-                    .ok_or_else(|| Error::NoCommitForRepository(repository.clone()))?,
-                &path,
-            )?;
-            let mut file = PathBuf::from(&repository);
-            file.push(path.as_path_buf());
+        let mut errors = vec![];
+        for stop in &tour.stops {
+            if !files.insert((&stop.repository, &stop.path)) {
+                continue;
+            }
+            let content = match self.lookup(&tour, stop) {
+                Ok(content) => content,
+                Err(e) if self.keep_going => {
+                    errors.push(e.in_stop(&tour, stop));
+                    continue;
+                }
+                Err(e) => return Err(e.in_stop(&tour, stop)),
+            };
+            let mut file = PathBuf::from(&stop.repository);
+            file.push(stop.path.as_path_buf());
 
             zip.start_file(
                 file.to_str()
@@ -51,9 +108,21 @@ impl<V: VCS> Package<V> {
             let _ = zip.write(&content)?;
         }
 
+        for (document, path) in linked {
+            zip.start_file(document, options)?;
+            let _ = zip.write(&fs::read(path)?)?;
+        }
+
         zip.start_file("tour.tour", options)?;
-        let _ = zip.write(tour_source.as_bytes())?;
+        if rewritten {
+            let _ = zip.write(serialize_tour(tour)?.as_bytes())?;
+        } else {
+            let _ = zip.write(tour_source.as_bytes())?;
+        }
 
+        if !errors.is_empty() {
+            return Err(Error::Multiple(errors));
+        }
         Ok(())
     }
 }
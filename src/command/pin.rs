@@ -0,0 +1,60 @@
+use crate::error::Result;
+use crate::serialize::serialize_tour;
+use crate::types::{Index, Tour};
+use crate::vcs::{is_pinned, VCS};
+use std::fs;
+use std::mem;
+use std::path::Path;
+
+pub struct Pin<V: VCS> {
+    vcs: V,
+    index: Index,
+}
+
+impl<V: VCS> Pin<V> {
+    pub fn new(vcs: V, index: Index) -> Self {
+        Pin { vcs, index }
+    }
+
+    pub fn process(&self, tour_path: &Path, mut tour: Tour) -> Result<()> {
+        for (repository, commit) in tour.repositories.iter_mut() {
+            if is_pinned(commit) {
+                continue;
+            }
+            let repo_path = self.index.get(repository)?;
+            let resolved = self
+                .vcs
+                .resolve_commit(repo_path.as_absolute_path(), commit)?;
+            println!("Pinned {} from '{}' to {}", repository, commit, resolved);
+            let reference = mem::replace(commit, resolved);
+            tour.pinned_refs.insert(repository.clone(), reference);
+        }
+        record_fingerprints(&self.vcs, &self.index, &mut tour)?;
+        fs::write(tour_path, serialize_tour(tour)?)?;
+        Ok(())
+    }
+}
+
+/// Records each repository's commit fingerprint and each stop's blob id, so the tour can be
+/// recovered if its commits are rewritten. Repositories whose commit is already gone keep
+/// whatever was recorded before.
+pub fn record_fingerprints<V: VCS>(vcs: &V, index: &Index, tour: &mut Tour) -> Result<()> {
+    for (repository, commit) in &tour.repositories {
+        let repo_path = index.get(repository)?;
+        if !vcs.commit_exists(repo_path.as_absolute_path(), commit)? {
+            continue;
+        }
+        let fingerprint = vcs.fingerprint(repo_path.as_absolute_path(), commit)?;
+        tour.fingerprints.insert(repository.clone(), fingerprint);
+        for stop in tour
+            .stops
+            .iter_mut()
+            .filter(|s| &s.repository == repository)
+        {
+            stop.blob = vcs
+                .blob_id(repo_path.as_absolute_path(), commit, &stop.path)
+                .ok();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use crate::error::{Error, Result};
+use crate::serialize::serialize_tour;
+use crate::types::{Index, Tour};
+use crate::vcs::{ReplacementReason, VCS};
+use std::fs;
+use std::path::Path;
+
+use super::pin::record_fingerprints;
+
+pub struct Recover<V: VCS> {
+    vcs: V,
+    index: Index,
+    apply: bool,
+}
+
+impl<V: VCS> Recover<V> {
+    pub fn new(vcs: V, index: Index, apply: bool) -> Self {
+        Recover { vcs, index, apply }
+    }
+
+    /// Proposes a replacement for every tour commit that no longer exists. With `apply`, the
+    /// replacements are written back to the tour.
+    pub fn process(&self, tour_path: &Path, mut tour: Tour) -> Result<()> {
+        let mut replaced = false;
+        let mut missing = 0;
+        for (repository, commit) in tour.repositories.iter_mut() {
+            let repo_path = self.index.get(repository)?;
+            if self
+                .vcs
+                .commit_exists(repo_path.as_absolute_path(), commit)?
+            {
+                continue;
+            }
+            let blobs = tour
+                .stops
+                .iter()
+                .filter(|s| &s.repository == repository)
+                .filter_map(|s| s.blob.as_ref().map(|b| (s.path.clone(), b.clone())))
+                .collect::<Vec<_>>();
+            let replacement = self.vcs.find_replacement(
+                repo_path.as_absolute_path(),
+                tour.fingerprints.get(repository),
+                &blobs,
+            )?;
+            match replacement {
+                Some(r) => {
+                    let reason = match r.reason {
+                        ReplacementReason::Tree => "same tree",
+                        ReplacementReason::Patch => "same patch",
+                        ReplacementReason::StopFiles => "same stop files",
+                    };
+                    println!(
+                        "{}: commit {} is gone; {} has the {}",
+                        repository, commit, r.commit, reason
+                    );
+                    if self.apply {
+                        *commit = r.commit;
+                        replaced = true;
+                    }
+                }
+                None => {
+                    missing += 1;
+                    println!(
+                        "{}: commit {} is gone and no replacement was found",
+                        repository, commit
+                    );
+                }
+            }
+        }
+
+        if replaced {
+            record_fingerprints(&self.vcs, &self.index, &mut tour)?;
+            fs::write(tour_path, serialize_tour(tour)?)?;
+            println!("Run `tourist check` to confirm the stops still point where they should.");
+        }
+        if missing > 0 {
+            return Err(Error::UnrecoverableCommits(missing));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::error::{Error, Result};
+use crate::relocate::{Location, Mode, Relocation, Relocator};
+use crate::serialize::serialize_tour;
+use crate::types::{Index, Tour};
+use crate::vcs::{is_pinned, VCS};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use super::pin::record_fingerprints;
+
+pub struct Refresh<V: VCS> {
+    vcs: V,
+    index: Index,
+    mode: Mode,
+}
+
+impl<V: VCS> Refresh<V> {
+    pub fn new(vcs: V, index: Index, mode: Mode) -> Self {
+        Refresh { vcs, index, mode }
+    }
+
+    pub fn vcs(&self) -> &V {
+        &self.vcs
+    }
+
+    /// Moves every stop to where its line lives at `target`, and the tour's repositories to
+    /// `target` along with them. Nothing is written if any stop cannot be relocated.
+    pub fn process(&self, tour_path: &Path, tour: Tour, target: &str) -> Result<()> {
+        self.refresh(tour_path, tour, target, None)
+    }
+
+    /// Like `process`, but only moves the stops in `repository`, and only that repository's
+    /// commit; the others keep theirs.
+    pub fn process_repository(
+        &self,
+        tour_path: &Path,
+        tour: Tour,
+        repository: &str,
+        target: &str,
+    ) -> Result<()> {
+        self.refresh(tour_path, tour, target, Some(repository))
+    }
+
+    fn refresh(
+        &self,
+        tour_path: &Path,
+        mut tour: Tour,
+        target: &str,
+        only: Option<&str>,
+    ) -> Result<()> {
+        let mut targets = HashMap::new();
+        for repository in tour
+            .repositories
+            .keys()
+            .filter(|repository| only.map_or(true, |only| only == repository.as_str()))
+        {
+            let repo_path = self.index.get(repository)?;
+            let commit = self
+                .vcs
+                .resolve_commit(repo_path.as_absolute_path(), target)?;
+            targets.insert(repository.clone(), commit);
+        }
+
+        let relocator = Relocator::new(&self.vcs, self.mode);
+        let mut lost = 0;
+        for stop in tour
+            .stops
+            .iter_mut()
+            .filter(|stop| only.map_or(true, |only| only == stop.repository))
+        {
+            let repo_path = self.index.get(&stop.repository)?;
+            let from = tour
+                .repositories
+                .get(&stop.repository)
+                .ok_or_else(|| Error::NoCommitForRepository(stop.repository.clone()))?;
+            let relocation = relocator.relocate(
+                repo_path.as_absolute_path(),
+                Location {
+                    path: stop.path.clone(),
+                    line: stop.line,
+                },
+                stop.anchor.as_ref(),
+                from,
+                &targets[&stop.repository],
+            )?;
+            match relocation {
+                Relocation::Found(location) => {
+                    stop.path = location.path;
+                    stop.line = location.line;
+                }
+                Relocation::Deleted { commit, nearest } => {
+                    lost += 1;
+                    match nearest {
+                        Some(n) => println!(
+                            "{}: line deleted in {}; nearest surviving line there is {}:{}",
+                            stop.title,
+                            commit,
+                            n.path.as_git_path(),
+                            n.line
+                        ),
+                        None => println!("{}: line deleted in {}", stop.title, commit),
+                    }
+                }
+            }
+        }
+        if lost > 0 {
+            return Err(Error::LostStops(lost));
+        }
+
+        for (repository, commit) in targets {
+            if is_pinned(target) {
+                tour.pinned_refs.remove(&repository);
+            } else {
+                tour.pinned_refs
+                    .insert(repository.clone(), target.to_owned());
+            }
+            tour.repositories.insert(repository, commit);
+        }
+        record_fingerprints(&self.vcs, &self.index, &mut tour)?;
+        fs::write(tour_path, serialize_tour(tour)?)?;
+        Ok(())
+    }
+}
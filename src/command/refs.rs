@@ -0,0 +1,101 @@
+use crate::error::{Error, Result};
+use crate::types::{Index, Tour};
+use crate::vcs::VCS;
+
+const REF_PREFIX: &str = "refs/tourist";
+
+/// Encodes `name` as a single ref name component. Letters, digits, `-` and `_` are kept and
+/// every other byte is percent-encoded, so distinct names never share a component and none of
+/// git's restrictions (`..`, a leading `.`, a `.lock` suffix) can be hit. `what` names the value
+/// in the error for an empty name.
+fn ref_component(name: &str, what: &str) -> Result<String> {
+    if name.is_empty() {
+        return Err(Error::EmptyRefComponent(what.to_owned()));
+    }
+    Ok(name
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+                char::from(b).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect())
+}
+
+fn tour_ref_prefix(tour: &Tour) -> Result<String> {
+    Ok(format!(
+        "{}/{}",
+        REF_PREFIX,
+        ref_component(&tour.id, "tour id")?
+    ))
+}
+
+pub struct PinRefs<V: VCS> {
+    vcs: V,
+    index: Index,
+}
+
+impl<V: VCS> PinRefs<V> {
+    pub fn new(vcs: V, index: Index) -> Self {
+        PinRefs { vcs, index }
+    }
+
+    /// Points `refs/tourist/<tour-id>/<repository>` at each of the tour's commits, so that git
+    /// never garbage collects them.
+    pub fn process(&self, tour: &Tour) -> Result<()> {
+        for (repository, commit) in &tour.repositories {
+            let repo_path = self.index.get(repository)?;
+            let name = format!(
+                "{}/{}",
+                tour_ref_prefix(tour)?,
+                ref_component(repository, "repository name")?
+            );
+            self.vcs
+                .create_ref(repo_path.as_absolute_path(), &name, commit)?;
+            println!("{}: {} -> {}", repository, name, commit);
+        }
+        Ok(())
+    }
+}
+
+pub struct UnpinRefs<V: VCS> {
+    vcs: V,
+    index: Index,
+}
+
+impl<V: VCS> UnpinRefs<V> {
+    pub fn new(vcs: V, index: Index) -> Self {
+        UnpinRefs { vcs, index }
+    }
+
+    /// Deletes every ref under `refs/tourist/<tour-id>/` in the tour's repositories.
+    pub fn process(&self, tour: &Tour) -> Result<()> {
+        for repository in tour.repositories.keys() {
+            let repo_path = self.index.get(repository)?;
+            let deleted = self
+                .vcs
+                .delete_refs(repo_path.as_absolute_path(), &tour_ref_prefix(tour)?)?;
+            println!("{}: deleted {} ref(s)", repository, deleted);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ref_component;
+
+    #[test]
+    fn ref_components_are_collision_free() {
+        let component = |name| ref_component(name, "tour id").unwrap();
+        assert_eq!(component("my-tour_1"), "my-tour_1");
+        assert_eq!(component("a/b"), "a%2Fb");
+        assert_ne!(component("a/b"), component("a_b"));
+        assert_ne!(component("a%2Fb"), component("a/b"));
+        assert_eq!(component("..hidden"), "%2E%2Ehidden");
+        assert_eq!(component("x.lock"), "x%2Elock");
+        assert!(ref_component("", "tour id").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::error::{Error, Result};
+use crate::relocate::{Location, MOVE_CONFIDENCE_THRESHOLD};
+use crate::types::path::RelativePathBuf;
+use crate::types::{Index, Stop, Tour};
+use crate::vcs::{Changes, LineFate, VCS};
+use std::collections::HashMap;
+
+/// What a tour's commits are compared against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Head,
+    Worktree,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StopStatus {
+    Unchanged,
+    /// The line is intact, but lines above it were added or removed.
+    Shifted(Location),
+    /// The line was rewritten in place.
+    Edited,
+    /// The line is intact, but its file was renamed or it was moved into another file.
+    Renamed(Location),
+    Deleted,
+}
+
+impl StopStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StopStatus::Unchanged => "unchanged",
+            StopStatus::Shifted(_) => "shifted",
+            StopStatus::Edited => "edited",
+            StopStatus::Renamed(_) => "renamed",
+            StopStatus::Deleted => "deleted",
+        }
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            StopStatus::Shifted(location) | StopStatus::Renamed(location) => Some(location),
+            _ => None,
+        }
+    }
+}
+
+const LABELS: [&str; 5] = ["unchanged", "shifted", "edited", "renamed", "deleted"];
+
+pub fn stop_status(changes: &Changes, path: &RelativePathBuf, line: usize) -> StopStatus {
+    match changes.map_line(path, line) {
+        LineFate::Unchanged => StopStatus::Unchanged,
+        LineFate::Moved { line } => StopStatus::Shifted(Location {
+            path: path.clone(),
+            line,
+        }),
+        LineFate::Renamed { path, line } => StopStatus::Renamed(Location { path, line }),
+        LineFate::Deleted {
+            moved: Some(moved), ..
+        } if moved.confidence >= MOVE_CONFIDENCE_THRESHOLD => StopStatus::Renamed(Location {
+            path: moved.path,
+            line: moved.line,
+        }),
+        LineFate::Deleted { .. } if changes.is_edited(path, line) => StopStatus::Edited,
+        LineFate::Deleted { .. } => StopStatus::Deleted,
+    }
+}
+
+pub struct Status<V: VCS> {
+    vcs: V,
+    index: Index,
+    target: Target,
+    porcelain: bool,
+}
+
+impl<V: VCS> Status<V> {
+    pub fn new(vcs: V, index: Index, target: Target, porcelain: bool) -> Self {
+        Status {
+            vcs,
+            index,
+            target,
+            porcelain,
+        }
+    }
+
+    /// Compares each repository's tour commit against HEAD or the worktree, and reports what
+    /// happened to the line under every stop.
+    pub fn process(&self, tour: &Tour) -> Result<()> {
+        let mut diffs = HashMap::new();
+        for (repository, commit) in &tour.repositories {
+            let repo_root = self.index.get(repository)?;
+            let repo_path = repo_root.as_absolute_path();
+            let commit = self.vcs.resolve_commit(repo_path, commit)?;
+            let changes = match self.target {
+                Target::Head => {
+                    let head = self.vcs.resolve_commit(repo_path, "HEAD")?;
+                    self.vcs.diff_with_version(repo_path, &commit, &head)?
+                }
+                Target::Worktree => self.vcs.diff_with_worktree(repo_path, &commit)?,
+            };
+            diffs.insert(repository.as_str(), changes);
+        }
+
+        let mut counts = HashMap::new();
+        for stop in &tour.stops {
+            let changes = diffs
+                .get(stop.repository.as_str())
+                .ok_or_else(|| Error::NoCommitForRepository(stop.repository.clone()))?;
+            let status = stop_status(changes, &stop.path, stop.line);
+            *counts.entry(status.label()).or_insert(0) += 1;
+            if self.porcelain {
+                println!("{}", porcelain_line(stop, &status));
+            } else if status != StopStatus::Unchanged {
+                println!("{}", compact_line(stop, &status));
+            }
+        }
+
+        if !self.porcelain {
+            let summary = LABELS
+                .iter()
+                .filter_map(|label| counts.get(label).map(|n| format!("{} {}", n, label)))
+                .collect::<Vec<_>>();
+            if summary.is_empty() {
+                println!("No stops.");
+            } else {
+                println!("{}", summary.join(", "));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn position(path: &RelativePathBuf, line: usize) -> String {
+    format!("{}:{}", path.as_git_path(), line)
+}
+
+/// `<status>\t<stop id>\t<repository>\t<path>:<line>[\t<new path>:<new line>]`
+fn porcelain_line(stop: &Stop, status: &StopStatus) -> String {
+    let mut fields = vec![
+        status.label().to_owned(),
+        stop.id.clone(),
+        stop.repository.clone(),
+        position(&stop.path, stop.line),
+    ];
+    if let Some(location) = status.location() {
+        fields.push(position(&location.path, location.line));
+    }
+    fields.join("\t")
+}
+
+fn compact_line(stop: &Stop, status: &StopStatus) -> String {
+    let old = format!("{}:{}", stop.repository, position(&stop.path, stop.line));
+    match status.location() {
+        Some(location) => format!(
+            "{:>9}: {} ({} -> {})",
+            status.label(),
+            stop.title,
+            old,
+            position(&location.path, location.line)
+        ),
+        None => format!("{:>9}: {} ({})", status.label(), stop.title, old),
+    }
+}
@@ -0,0 +1,32 @@
+use crate::error::{Error, Result};
+use crate::serialize::validate;
+use std::fs;
+use std::path::PathBuf;
+
+pub struct Validate {
+    strict: bool,
+}
+
+impl Validate {
+    pub fn new(strict: bool) -> Self {
+        Validate { strict }
+    }
+
+    /// Checks each tour file, reporting every problem found rather than stopping at the first.
+    pub fn process(&self, tour_files: &[PathBuf]) -> Result<()> {
+        let mut errors = vec![];
+        for tour_file in tour_files {
+            let problems = validate(&fs::read_to_string(tour_file)?, self.strict);
+            if problems.is_empty() {
+                println!("{}: ok", tour_file.display());
+            } else {
+                errors.push(Error::InvalidTour(Some(tour_file.clone()), problems));
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Multiple(errors)),
+        }
+    }
+}
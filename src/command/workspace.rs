@@ -0,0 +1,71 @@
+use crate::diagnostic;
+use crate::error::Result;
+use crate::vcs::VCS;
+use crate::workspace::Workspace;
+
+pub struct WorkspaceSync<V: VCS> {
+    vcs: V,
+}
+
+impl<V: VCS> WorkspaceSync<V> {
+    pub fn new(vcs: V) -> Self {
+        WorkspaceSync { vcs }
+    }
+
+    /// Clones every repository in the workspace that is not checked out yet from its mirror.
+    pub fn process(&self, workspace: &Workspace) -> Result<()> {
+        for (name, repository) in &workspace.repositories {
+            let path = workspace.repository_path(repository);
+            if path.exists() {
+                println!("{}: present at {}", name, path.display());
+                continue;
+            }
+            match workspace.mirror_source(name, repository)? {
+                Some(source) => {
+                    self.vcs.clone_repository(&source, &path)?;
+                    println!("{}: cloned {} into {}", name, source, path.display());
+                }
+                None => diagnostic::warning(
+                    "no-mirror",
+                    &format!(
+                        "{} is missing from {} and has no mirror to clone it from.",
+                        name,
+                        path.display()
+                    ),
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkspaceSync;
+    use crate::vcs::testing::{add_files, commit};
+    use crate::vcs::Git;
+    use crate::workspace::Workspace;
+    use git2::Repository;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn clones_missing_repositories_from_mirrors() {
+        let mirror = TempDir::new("mirror").unwrap().into_path();
+        let repo = Repository::init(&mirror).unwrap();
+        fs::write(mirror.join("a.txt"), "a").unwrap();
+        commit(&repo, add_files(&repo, vec!["a.txt"]).unwrap(), "init").unwrap();
+
+        let root = TempDir::new("workspace").unwrap().into_path();
+        let manifest = format!(
+            r#"{{ "repositories": {{ "lib": {{ "path": "deps/lib", "mirror": "{}" }} }} }}"#,
+            mirror.display()
+        );
+        let workspace = Workspace::parse(&manifest, &root).unwrap();
+        WorkspaceSync::new(Git::new()).process(&workspace).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("deps/lib/a.txt")).unwrap(),
+            "a"
+        );
+    }
+}
@@ -0,0 +1,588 @@
+use crate::command::DumpFormat;
+use crate::error::{Error, Result};
+use crate::lint;
+use crate::types::path::AbsolutePathBuf;
+use crate::types::{Index, IndexRule, RuleKey};
+use crate::vcs::SimilarityOptions;
+use crate::workspace::Workspace;
+use dirs;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Defaults for `tourist dump`, used when the corresponding flag is not given.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub above: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub below: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tab_width: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_going: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<DumpFormat>,
+}
+
+/// Defaults for `tourist package`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub out: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_going: Option<bool>,
+}
+
+/// How diffs pair up files that were renamed or copied with edits, for every command that
+/// relocates stops.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renames: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copies: Option<bool>,
+    /// The similarity percentage above which files are paired, as in `git diff -M`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<u16>,
+}
+
+impl DiffConfig {
+    pub fn similarity(&self) -> SimilarityOptions {
+        let defaults = SimilarityOptions::default();
+        SimilarityOptions {
+            renames: self.renames.unwrap_or(defaults.renames),
+            copies: self.copies.unwrap_or(defaults.copies),
+            threshold: self.similarity.unwrap_or(defaults.threshold),
+        }
+    }
+}
+
+/// Settings for `tourist lint`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LintConfig {
+    /// The level of each rule, by rule name, overriding its default.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rules: BTreeMap<String, lint::Level>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_length: Option<usize>,
+}
+
+impl LintConfig {
+    pub fn settings(&self) -> lint::Settings {
+        lint::Settings {
+            levels: self.rules.clone(),
+            max_body_length: self
+                .max_body_length
+                .unwrap_or(lint::DEFAULT_MAX_BODY_LENGTH),
+        }
+    }
+}
+
+/// How `tourist open` starts an editor.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EditorConfig {
+    /// The command that opens a file at a line, with `{path}` and `{line}` filled in, e.g.
+    /// `code -g {path}:{line}`. Defaults to `$VISUAL` or `$EDITOR` with `+{line} {path}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+/// Directories whose immediate subdirectories are git repositories, indexed by directory name.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<PathBuf>,
+}
+
+/// A pattern-based index entry, tried when a repository is not in the index by name.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum IndexRuleConfig {
+    Name { name: String, path: String },
+    Remote { remote: String, path: String },
+}
+
+impl IndexRuleConfig {
+    fn path_mut(&mut self) -> &mut String {
+        match self {
+            IndexRuleConfig::Name { path, .. } | IndexRuleConfig::Remote { path, .. } => path,
+        }
+    }
+
+    fn to_rule(&self) -> IndexRule {
+        match self.clone() {
+            IndexRuleConfig::Name { name, path } => IndexRule {
+                key: RuleKey::Name(name),
+                path,
+            },
+            IndexRuleConfig::Remote { remote, path } => IndexRule {
+                key: RuleKey::Remote(remote),
+                path,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    pub version: u32,
+    #[serde(default)]
+    pub index: HashMap<String, PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub index_rules: Vec<IndexRuleConfig>,
+    #[serde(default)]
+    pub dump: DumpConfig,
+    #[serde(default)]
+    pub package: PackageConfig,
+    #[serde(default)]
+    pub diff: DiffConfig,
+    #[serde(default)]
+    pub lint: LintConfig,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    #[serde(default)]
+    pub editor: EditorConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            index: HashMap::new(),
+            index_rules: vec![],
+            dump: DumpConfig::default(),
+            package: PackageConfig::default(),
+            diff: DiffConfig::default(),
+            lint: LintConfig::default(),
+            discovery: DiscoveryConfig::default(),
+            editor: EditorConfig::default(),
+        }
+    }
+}
+
+/// Whether `value` is in the original config format: an object whose every value is a path
+/// string. Anything else must be a versioned config.
+fn is_flat_index(value: &Value) -> bool {
+    value
+        .as_object()
+        .map_or(false, |map| map.values().all(Value::is_string))
+}
+
+impl Config {
+    /// Parses a config file. The original format, a flat map from repository names to paths, is
+    /// read as a config holding only an index.
+    pub fn parse(source: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(source)?;
+        if is_flat_index(&value) {
+            return Ok(Config {
+                index: serde_json::from_value(value)?,
+                ..Config::default()
+            });
+        }
+        let config: Config = serde_json::from_value(value)?;
+        if config.version > CONFIG_VERSION {
+            return Err(Error::UnsupportedConfigVersion(config.version));
+        }
+        Ok(config)
+    }
+
+    /// Makes relative index paths and discovery roots relative to `dir`, the directory holding
+    /// the config file, so a project can check in its own repository map.
+    fn resolve_paths(&mut self, dir: &Path) {
+        for path in self
+            .index
+            .values_mut()
+            .chain(self.discovery.roots.iter_mut())
+        {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        }
+        for rule in &mut self.index_rules {
+            let path = rule.path_mut();
+            if !path.starts_with('~') && Path::new(path.as_str()).is_relative() {
+                *path = dir.join(path.as_str()).to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    /// The repositories tours can refer to: those listed in the index, then those found under the
+    /// discovery roots, then those matched by the index rules. Relative paths are ignored.
+    pub fn index(&self) -> Index {
+        let mut index = self
+            .index
+            .iter()
+            .filter_map(|(k, v)| AbsolutePathBuf::new(v.clone()).map(|ap| (k.to_owned(), ap)))
+            .collect::<HashMap<_, _>>();
+        for root in &self.discovery.roots {
+            let entries = match fs::read_dir(root) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                let name = match path.file_name().and_then(|n| n.to_str()) {
+                    Some(name) => name.to_owned(),
+                    None => continue,
+                };
+                if !path.join(".git").exists() || index.contains_key(&name) {
+                    continue;
+                }
+                if let Some(path) = AbsolutePathBuf::new(path) {
+                    index.insert(name, path);
+                }
+            }
+        }
+        Index::new(
+            index,
+            self.index_rules
+                .iter()
+                .map(IndexRuleConfig::to_rule)
+                .collect(),
+        )
+    }
+}
+
+/// The file name looked for in the tour's directory and its ancestors.
+pub const PROJECT_CONFIG: &str = ".tourist.json";
+
+/// A config file that contributes to the effective config.
+pub struct Layer {
+    pub path: PathBuf,
+    pub config: Config,
+}
+
+/// The effective config, with the layer each value came from and the value as text.
+pub struct Resolved {
+    pub config: Config,
+    pub sources: BTreeMap<String, (String, PathBuf)>,
+}
+
+fn existing(path: PathBuf) -> Option<PathBuf> {
+    if path.exists() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG))
+        .find(|path| path.exists())
+}
+
+/// The config files that apply to a tour in `start`, from lowest to highest precedence: the
+/// nearest project file, the XDG config file, the home dotfile, and `TOURIST_CONFIG`.
+pub fn layer_paths(start: &Path) -> Vec<PathBuf> {
+    vec![
+        find_project_config(start),
+        dirs::config_dir().and_then(|dir| existing(dir.join("tourist").join("config.json"))),
+        dirs::home_dir().and_then(|dir| existing(dir.join(".tourist"))),
+        env::var("TOURIST_CONFIG")
+            .ok()
+            .and_then(|val| existing(PathBuf::from(val))),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn read_layer(path: PathBuf) -> Result<Layer> {
+    let mut config = Config::parse(&fs::read_to_string(&path)?)?;
+    if let Some(dir) = path.parent() {
+        config.resolve_paths(dir);
+    }
+    Ok(Layer { path, config })
+}
+
+fn set_option<T: Clone + fmt::Debug>(
+    to: &mut Option<T>,
+    from: &Option<T>,
+    key: &str,
+    layer: &Path,
+    sources: &mut BTreeMap<String, (String, PathBuf)>,
+) {
+    if let Some(value) = from {
+        sources.insert(key.to_owned(), (format!("{:?}", value), layer.to_owned()));
+        *to = Some(value.clone());
+    }
+}
+
+/// Merges `layers` in order, each overriding the values set by the ones before it. Index entries
+/// are merged by repository name, and discovery roots accumulate.
+pub fn merge(layers: &[Layer]) -> Resolved {
+    let mut config = Config::default();
+    let mut sources = BTreeMap::new();
+    for Layer {
+        path,
+        config: layer,
+    } in layers
+    {
+        for (name, repo_path) in &layer.index {
+            config.index.insert(name.clone(), repo_path.clone());
+            sources.insert(
+                format!("index.{}", name),
+                (format!("{:?}", repo_path), path.clone()),
+            );
+        }
+        for rule in &layer.index_rules {
+            let (key, rule_path) = match rule {
+                IndexRuleConfig::Name { name, path } => (name, path),
+                IndexRuleConfig::Remote { remote, path } => (remote, path),
+            };
+            sources.insert(
+                format!("indexRules.{}", key),
+                (format!("{:?}", rule_path), path.clone()),
+            );
+        }
+        config
+            .index_rules
+            .splice(0..0, layer.index_rules.iter().cloned());
+        let dump = &layer.dump;
+        set_option(
+            &mut config.dump.context,
+            &dump.context,
+            "dump.context",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.dump.above,
+            &dump.above,
+            "dump.above",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.dump.below,
+            &dump.below,
+            "dump.below",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.dump.tab_width,
+            &dump.tab_width,
+            "dump.tabWidth",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.dump.keep_going,
+            &dump.keep_going,
+            "dump.keepGoing",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.dump.format,
+            &dump.format,
+            "dump.format",
+            path,
+            &mut sources,
+        );
+        let package = &layer.package;
+        set_option(
+            &mut config.package.out,
+            &package.out,
+            "package.out",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.package.keep_going,
+            &package.keep_going,
+            "package.keepGoing",
+            path,
+            &mut sources,
+        );
+        let diff = &layer.diff;
+        set_option(
+            &mut config.diff.renames,
+            &diff.renames,
+            "diff.renames",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.diff.copies,
+            &diff.copies,
+            "diff.copies",
+            path,
+            &mut sources,
+        );
+        set_option(
+            &mut config.diff.similarity,
+            &diff.similarity,
+            "diff.similarity",
+            path,
+            &mut sources,
+        );
+        for (rule, level) in &layer.lint.rules {
+            config.lint.rules.insert(rule.clone(), *level);
+            sources.insert(
+                format!("lint.rules.{}", rule),
+                (format!("{:?}", level), path.clone()),
+            );
+        }
+        set_option(
+            &mut config.lint.max_body_length,
+            &layer.lint.max_body_length,
+            "lint.maxBodyLength",
+            path,
+            &mut sources,
+        );
+        for (i, root) in layer.discovery.roots.iter().enumerate() {
+            sources.insert(
+                format!("discovery.roots.{}", config.discovery.roots.len() + i),
+                (format!("{:?}", root), path.clone()),
+            );
+        }
+        config
+            .discovery
+            .roots
+            .extend(layer.discovery.roots.iter().cloned());
+        set_option(
+            &mut config.editor.command,
+            &layer.editor.command,
+            "editor.command",
+            path,
+            &mut sources,
+        );
+    }
+    Resolved { config, sources }
+}
+
+/// Reads every config layer that applies to a tour in `start`. The nearest workspace manifest, if
+/// any, comes first, so any config file can override its paths.
+pub fn layers(start: &Path) -> Result<Vec<Layer>> {
+    let mut layers = vec![];
+    if let Some((path, workspace)) = Workspace::find(start)? {
+        layers.push(workspace.into_layer(path));
+    }
+    for path in layer_paths(start) {
+        layers.push(read_layer(path)?);
+    }
+    Ok(layers)
+}
+
+pub fn load(start: &Path) -> Result<Config> {
+    Ok(merge(&layers(start)?).config)
+}
+
+/// Rewrites the config file at `path` in the current format. Returns false if it already was.
+pub fn migrate(path: &Path) -> Result<bool> {
+    let source = fs::read_to_string(path)?;
+    let config = Config::parse(&source)?;
+    if !is_flat_index(&serde_json::from_str(&source)?) {
+        return Ok(false);
+    }
+    fs::write(path.with_extension("bak"), &source)?;
+    fs::write(path, serde_json::to_string_pretty(&config)?)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_project_config, merge, Config, Layer, CONFIG_VERSION, PROJECT_CONFIG};
+    use crate::command::DumpFormat;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    fn reads_flat_index_as_config() {
+        let config = Config::parse(r#"{ "repo": "/src/repo" }"#).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.index["repo"], PathBuf::from("/src/repo"));
+        assert_eq!(config.dump.above, None);
+        // A config that is not a flat map of paths must say which version it is.
+        assert!(Config::parse(r#"{ "index": { "repo": "/src/repo" } }"#).is_err());
+        assert!(Config::parse(r#"{ "repo": "/src/repo", "dump": {} }"#).is_err());
+    }
+
+    #[test]
+    fn reads_sections() {
+        let config = Config::parse(
+            r#"{
+                "version": 1,
+                "index": { "repo": "/src/repo" },
+                "dump": {
+                    "context": true, "above": 2, "tabWidth": 4, "keepGoing": true,
+                    "format": "json"
+                },
+                "package": { "out": "tour.pkg" },
+                "editor": { "command": "vim +{line} {path}" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.dump.format, Some(DumpFormat::Json));
+        assert_eq!(config.dump.context, Some(true));
+        assert_eq!(config.dump.tab_width, Some(4));
+        assert_eq!(config.dump.keep_going, Some(true));
+        assert_eq!(config.package.out, Some(PathBuf::from("tour.pkg")));
+        assert_eq!(config.editor.command, Some("vim +{line} {path}".to_owned()));
+        assert!(Config::parse(r#"{ "version": 2 }"#).is_err());
+        assert!(Config::parse(r#"{ "version": 1, "colour": "red" }"#).is_err());
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let layer = |path: &str, source: &str| {
+            let mut config = Config::parse(source).unwrap();
+            config.resolve_paths(Path::new(path));
+            Layer {
+                path: PathBuf::from(path).join(PROJECT_CONFIG),
+                config,
+            }
+        };
+        let project = layer(
+            "/src/mono",
+            r#"{ "version": 1, "index": { "app": ".", "lib": "/src/lib" },
+                 "dump": { "above": 2, "below": 2 } }"#,
+        );
+        let user = layer(
+            "/home/me",
+            r#"{ "version": 1, "index": { "lib": "/home/me/lib" }, "dump": { "above": 5 } }"#,
+        );
+        let resolved = merge(&[project, user]);
+        assert_eq!(resolved.config.index["app"], PathBuf::from("/src/mono/."));
+        assert_eq!(resolved.config.index["lib"], PathBuf::from("/home/me/lib"));
+        assert_eq!(resolved.config.dump.above, Some(5));
+        assert_eq!(resolved.config.dump.below, Some(2));
+        assert_eq!(
+            resolved.sources["dump.above"].1,
+            PathBuf::from("/home/me/.tourist.json")
+        );
+        assert_eq!(
+            resolved.sources["dump.below"].1,
+            PathBuf::from("/src/mono/.tourist.json")
+        );
+    }
+
+    #[test]
+    fn finds_project_config_in_ancestors() {
+        let root = TempDir::new("project").unwrap().into_path();
+        let tours = root.join("docs").join("tours");
+        fs::create_dir_all(&tours).unwrap();
+        fs::write(root.join(PROJECT_CONFIG), "{}").unwrap();
+        assert_eq!(find_project_config(&tours), Some(root.join(PROJECT_CONFIG)));
+    }
+}
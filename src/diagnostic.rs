@@ -0,0 +1,74 @@
+use crate::error::Error;
+use crate::lint::Finding;
+use serde_json::json;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How errors and warnings are written to stderr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageFormat {
+    Human,
+    /// One JSON object per line, each with a `level`, a stable `code` and a `message`.
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!("unknown message format '{}'", s)),
+        }
+    }
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: MessageFormat) {
+    JSON.store(format == MessageFormat::Json, Ordering::Relaxed);
+}
+
+/// Reports something suspicious that does not stop the command.
+pub fn warning(code: &str, message: &str) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!(
+            "{}",
+            json!({ "level": "warning", "code": code, "message": message })
+        );
+    } else {
+        eprintln!("warning: {}", message);
+    }
+}
+
+/// Reports a lint finding in the tour file at `file`.
+pub fn finding(file: &Path, finding: &Finding) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!(
+            "{}",
+            json!({
+                "level": finding.level.to_string(),
+                "code": finding.rule,
+                "message": finding.message,
+                "file": file.display().to_string(),
+                "stop": finding.stop,
+            })
+        );
+    } else {
+        eprintln!("{}: {}", file.display(), finding);
+    }
+}
+
+/// Reports the error a command failed with.
+pub fn error(e: &Error) {
+    if JSON.load(Ordering::Relaxed) {
+        let mut value = e.to_json();
+        value["level"] = json!("error");
+        value["exitCode"] = json!(e.exit_code());
+        eprintln!("{}", value);
+    } else {
+        eprintln!("{}", e);
+    }
+}
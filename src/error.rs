@@ -1,9 +1,39 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::serialize::Problem;
+use crate::types::{Stop, Tour};
+use serde_json::{json, Value};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Identifies the stop an error happened while processing.
+#[derive(Debug)]
+pub struct StopContext {
+    pub tour_id: String,
+    pub stop_id: String,
+    pub title: String,
+    pub location: String,
+}
+
+impl StopContext {
+    pub fn new(tour: &Tour, stop: &Stop) -> Self {
+        StopContext {
+            tour_id: tour.id.clone(),
+            stop_id: stop.id.clone(),
+            title: stop.title.clone(),
+            location: format!(
+                "{}:{}:{}",
+                stop.repository,
+                stop.path.as_git_path(),
+                stop.line
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Git2(git2::Error),
@@ -11,9 +41,163 @@ pub enum Error {
     IO(io::Error),
     Serde(serde_json::Error),
     Zip(zip::result::ZipError),
-    NotInIndex(String),
+    /// The repository, and the index rules that were tried for it.
+    NotInIndex(String, Vec<String>),
     NoCommitForRepository(String),
     RevParse(String),
+    SubmoduleNotCheckedOut(String),
+    LfsObjectMissing(String),
+    StaleStops(usize),
+    NotFirstParentAncestor(String, String),
+    LostStops(usize),
+    CommitNotFound(String),
+    UnrecoverableCommits(usize),
+    HookExists(PathBuf),
+    BrokenStops(usize),
+    InStop(StopContext, Box<Error>),
+    Multiple(Vec<Error>),
+    InvalidTour(Option<PathBuf>, Vec<Problem>),
+    UnsupportedConfigVersion(u32),
+    NoWorkspace(PathBuf),
+    LintFailed(usize),
+    /// What was empty, e.g. "tour id".
+    EmptyRefComponent(String),
+    /// The repository name two or more repositories would be renamed to.
+    RenameConflict(String),
+    /// A `--repo` override naming a path that is not a directory.
+    RepoNotADirectory(String, PathBuf),
+    /// A workspace repository whose mirror is neither a local path nor a `file://` URL.
+    UnsupportedMirror(String, String),
+    /// A stop id that is not in the tour.
+    UnknownStop(String),
+    NoEditor,
+    /// The editor command, which exited unsuccessfully.
+    EditorFailed(String),
+}
+
+/// Process exit codes, one per category of error.
+pub const EXIT_OTHER: i32 = 1;
+pub const EXIT_INVALID_TOUR: i32 = 2;
+pub const EXIT_CONFIG: i32 = 3;
+pub const EXIT_REPOSITORY: i32 = 4;
+pub const EXIT_STALE_TOUR: i32 = 5;
+pub const EXIT_IO: i32 = 6;
+
+impl Error {
+    /// A stable identifier for the kind of error, for tools that consume `--message-format json`.
+    pub fn code(&self) -> &'static str {
+        use Error::*;
+        match self {
+            Git2(_) => "git",
+            Utf8(_) => "utf8",
+            IO(_) => "io",
+            Serde(_) => "json",
+            Zip(_) => "zip",
+            NotInIndex(_, _) => "not-in-index",
+            NoCommitForRepository(_) => "no-commit-for-repository",
+            RevParse(_) => "rev-parse",
+            SubmoduleNotCheckedOut(_) => "submodule-not-checked-out",
+            LfsObjectMissing(_) => "lfs-object-missing",
+            StaleStops(_) => "stale-stops",
+            NotFirstParentAncestor(_, _) => "not-first-parent-ancestor",
+            LostStops(_) => "lost-stops",
+            CommitNotFound(_) => "commit-not-found",
+            UnrecoverableCommits(_) => "unrecoverable-commits",
+            HookExists(_) => "hook-exists",
+            BrokenStops(_) => "broken-stops",
+            InStop(_, e) => e.code(),
+            Multiple(_) => "multiple",
+            InvalidTour(_, _) => "invalid-tour",
+            UnsupportedConfigVersion(_) => "unsupported-config-version",
+            NoWorkspace(_) => "no-workspace",
+            LintFailed(_) => "lint-failed",
+            EmptyRefComponent(_) => "empty-ref-component",
+            RenameConflict(_) => "rename-conflict",
+            RepoNotADirectory(_, _) => "repo-not-a-directory",
+            UnsupportedMirror(_, _) => "unsupported-mirror",
+            UnknownStop(_) => "unknown-stop",
+            NoEditor => "no-editor",
+            EditorFailed(_) => "editor-failed",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        use Error::*;
+        match self {
+            InvalidTour(_, _) | LintFailed(_) | EmptyRefComponent(_) => EXIT_INVALID_TOUR,
+            NotInIndex(_, _)
+            | NoCommitForRepository(_)
+            | HookExists(_)
+            | UnsupportedConfigVersion(_)
+            | NoWorkspace(_)
+            | RenameConflict(_)
+            | RepoNotADirectory(_, _)
+            | UnsupportedMirror(_, _)
+            | NoEditor => EXIT_CONFIG,
+            Git2(_)
+            | RevParse(_)
+            | SubmoduleNotCheckedOut(_)
+            | LfsObjectMissing(_)
+            | NotFirstParentAncestor(_, _)
+            | CommitNotFound(_) => EXIT_REPOSITORY,
+            StaleStops(_) | LostStops(_) | UnrecoverableCommits(_) | BrokenStops(_) => {
+                EXIT_STALE_TOUR
+            }
+            IO(_) | Zip(_) => EXIT_IO,
+            // Decoding and JSON errors come from tours, config files, manifests and output alike,
+            // so they say nothing about which category failed.
+            Utf8(_) | Serde(_) | UnknownStop(_) | EditorFailed(_) => EXIT_OTHER,
+            InStop(_, e) => e.exit_code(),
+            Multiple(errors) => {
+                let codes = errors.iter().map(Error::exit_code).collect::<Vec<_>>();
+                match codes.first() {
+                    Some(code) if codes.iter().all(|c| c == code) => *code,
+                    _ => EXIT_OTHER,
+                }
+            }
+        }
+    }
+
+    /// The error as a JSON object with its `code`, `message` and any structured details.
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({ "code": self.code(), "message": self.to_string() });
+        match self {
+            Error::InStop(context, e) => {
+                value["stop"] = json!({
+                    "tourId": context.tour_id,
+                    "stopId": context.stop_id,
+                    "title": context.title,
+                    "location": context.location,
+                });
+                value["cause"] = e.to_json();
+            }
+            Error::Multiple(errors) => {
+                value["errors"] = Value::Array(errors.iter().map(Error::to_json).collect());
+            }
+            Error::InvalidTour(path, problems) => {
+                value["file"] = json!(path.as_ref().map(|p| p.display().to_string()));
+                value["problems"] = problems
+                    .iter()
+                    .map(|p| json!({ "line": p.line, "column": p.column, "message": p.message }))
+                    .collect();
+            }
+            _ => {}
+        }
+        value
+    }
+
+    /// Attaches the tour and stop being processed to this error.
+    pub fn in_stop(self, tour: &Tour, stop: &Stop) -> Error {
+        Error::InStop(StopContext::new(tour, stop), Box::new(self))
+    }
+
+    /// Attaches the tour file that failed to parse to this error.
+    pub fn in_file(self, path: &Path) -> Error {
+        match self {
+            Error::InvalidTour(_, problems) => Error::InvalidTour(Some(path.to_owned()), problems),
+            e => e,
+        }
+    }
 }
 
 impl error::Error for Error {
@@ -25,9 +209,31 @@ impl error::Error for Error {
             Git2(e) => Some(e),
             Utf8(e) => Some(e),
             Zip(e) => Some(e),
-            NotInIndex(_) => None,
+            NotInIndex(_, _) => None,
             NoCommitForRepository(_) => None,
             RevParse(_) => None,
+            SubmoduleNotCheckedOut(_) => None,
+            LfsObjectMissing(_) => None,
+            StaleStops(_) => None,
+            NotFirstParentAncestor(_, _) => None,
+            LostStops(_) => None,
+            CommitNotFound(_) => None,
+            UnrecoverableCommits(_) => None,
+            HookExists(_) => None,
+            BrokenStops(_) => None,
+            InStop(_, e) => Some(e.as_ref()),
+            Multiple(_) => None,
+            InvalidTour(_, _) => None,
+            UnsupportedConfigVersion(_) => None,
+            NoWorkspace(_) => None,
+            LintFailed(_) => None,
+            EmptyRefComponent(_) => None,
+            RenameConflict(_) => None,
+            RepoNotADirectory(_, _) => None,
+            UnsupportedMirror(_, _) => None,
+            UnknownStop(_) => None,
+            NoEditor => None,
+            EditorFailed(_) => None,
         }
     }
 }
@@ -41,9 +247,109 @@ impl fmt::Display for Error {
             Git2(e) => e.fmt(f),
             Utf8(e) => e.fmt(f),
             Zip(e) => e.fmt(f),
-            NotInIndex(s) => write!(f, "Could not find repository '{}' in index.", s),
+            NotInIndex(s, tried) => {
+                write!(f, "Could not find repository '{}' in index.", s)?;
+                if !tried.is_empty() {
+                    write!(f, " Tried rules: {}.", tried.join("; "))?;
+                }
+                Ok(())
+            }
             NoCommitForRepository(s) => write!(f, "Could not find commit for repository '{}'.", s),
             RevParse(rev) => write!(f, "Reference '{}' does not point to a blob.", rev),
+            SubmoduleNotCheckedOut(path) => write!(f, "Submodule '{}' is not checked out.", path),
+            LfsObjectMissing(oid) => write!(
+                f,
+                "Git LFS object '{}' is not in the local object store. Try `git lfs fetch`.",
+                oid
+            ),
+            StaleStops(n) => write!(f, "{} stop(s) no longer match their anchors.", n),
+            NotFirstParentAncestor(from, to) => write!(
+                f,
+                "Commit '{}' is not in the first-parent history of '{}'.",
+                from, to
+            ),
+            LostStops(n) => write!(f, "{} stop(s) could not be relocated.", n),
+            CommitNotFound(commit) => write!(
+                f,
+                "Commit '{}' no longer exists. Run `tourist recover` to look for a replacement.",
+                commit
+            ),
+            UnrecoverableCommits(n) => {
+                write!(f, "No replacement found for {} missing commit(s).", n)
+            }
+            HookExists(path) => write!(
+                f,
+                "A hook not installed by tourist already exists at '{}'.",
+                path.display()
+            ),
+            BrokenStops(n) => write!(
+                f,
+                "This commit deletes the lines {} stop(s) point at. Use `git commit --no-verify` \
+                 to commit anyway.",
+                n
+            ),
+            InStop(context, e) => write!(
+                f,
+                "In stop '{}' ({}) of tour '{}', at {}: {}",
+                context.title, context.stop_id, context.tour_id, context.location, e
+            ),
+            Multiple(errors) => {
+                write!(f, "{} error(s):", errors.len())?;
+                for e in errors {
+                    write!(f, "\n  {}", e)?;
+                }
+                Ok(())
+            }
+            UnsupportedConfigVersion(v) => write!(
+                f,
+                "Config file version {} is newer than this version of tourist supports.",
+                v
+            ),
+            NoWorkspace(dir) => write!(
+                f,
+                "No tourist.workspace.json found in '{}' or its parents.",
+                dir.display()
+            ),
+            LintFailed(n) => write!(f, "{} lint finding(s) must be fixed.", n),
+            EmptyRefComponent(what) => {
+                write!(
+                    f,
+                    "The {} is empty, so it cannot be used in a ref name.",
+                    what
+                )
+            }
+            RenameConflict(name) => write!(
+                f,
+                "More than one repository would be renamed to '{}'.",
+                name
+            ),
+            RepoNotADirectory(name, path) => write!(
+                f,
+                "The path given for repository '{}', {}, is not a directory.",
+                name,
+                path.display()
+            ),
+            UnsupportedMirror(name, mirror) => write!(
+                f,
+                "The mirror of repository '{}', {}, is not a local path or file:// URL.",
+                name, mirror
+            ),
+            UnknownStop(id) => write!(f, "The tour has no stop with id '{}'.", id),
+            NoEditor => write!(
+                f,
+                "No editor is configured. Set editor.command in the config file, or $EDITOR."
+            ),
+            EditorFailed(command) => write!(f, "The editor command '{}' failed.", command),
+            InvalidTour(path, problems) => {
+                let file = path
+                    .as_ref()
+                    .map_or_else(|| "<tour>".to_owned(), |p| p.display().to_string());
+                write!(f, "Invalid tour file {}:", file)?;
+                for problem in problems {
+                    write!(f, "\n{}:{}", file, problem)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -77,3 +383,32 @@ impl From<std::str::Utf8Error> for Error {
         Error::Utf8(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, StopContext, EXIT_CONFIG, EXIT_OTHER};
+
+    #[test]
+    fn wrapped_errors_keep_their_code() {
+        let context = StopContext {
+            tour_id: "tour".to_owned(),
+            stop_id: "1".to_owned(),
+            title: "A".to_owned(),
+            location: "repo:a.rs:1".to_owned(),
+        };
+        let e = Error::InStop(
+            context,
+            Box::new(Error::NotInIndex("repo".to_owned(), vec![])),
+        );
+        assert_eq!(e.code(), "not-in-index");
+        assert_eq!(e.exit_code(), EXIT_CONFIG);
+        assert_eq!(e.to_json()["stop"]["stopId"], "1");
+        assert_eq!(e.to_json()["cause"]["code"], "not-in-index");
+
+        let mixed = Error::Multiple(vec![e, Error::LostStops(1)]);
+        assert_eq!(mixed.exit_code(), EXIT_OTHER);
+
+        let json = serde_json::from_str::<u32>("x").unwrap_err();
+        assert_eq!(Error::from(json).exit_code(), EXIT_OTHER);
+    }
+}
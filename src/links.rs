@@ -0,0 +1,296 @@
+use crate::error::Result;
+use crate::markdown::{self, Link, Target};
+use crate::serialize::read_tour;
+use crate::types::Tour;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A link from a stop body to a stop: `stop:<stop-id>`, `tour:<tour-id>#<stop-id>`, or
+/// `tour:<tour-id>` for a whole tour.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StopLink<'a> {
+    pub tour: Option<&'a str>,
+    pub stop: Option<&'a str>,
+}
+
+impl<'a> StopLink<'a> {
+    pub fn parse(target: &'a str) -> Option<Self> {
+        if target.starts_with("stop:") {
+            return Some(StopLink {
+                tour: None,
+                stop: Some(&target["stop:".len()..]),
+            });
+        }
+        if !target.starts_with("tour:") {
+            return None;
+        }
+        let rest = &target["tour:".len()..];
+        Some(match rest.find('#') {
+            Some(i) => StopLink {
+                tour: Some(&rest[..i]),
+                stop: Some(&rest[i + 1..]),
+            },
+            None => StopLink {
+                tour: Some(rest),
+                stop: None,
+            },
+        })
+    }
+}
+
+/// Appends `id` to `prefix`. Characters other than ASCII letters, digits and `-` are written as
+/// `_` and their UTF-8 bytes in hex, so the result can be used unescaped in HTML, in a URL and
+/// as a file name, and distinct ids give distinct results.
+fn escape(prefix: &str, id: &str) -> String {
+    let mut escaped = prefix.to_owned();
+    for b in id.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("_{:02X}", b));
+        }
+    }
+    escaped
+}
+
+/// The id of the HTML anchor a dumped stop is given.
+pub fn stop_anchor(stop_id: &str) -> String {
+    escape("stop-", stop_id)
+}
+
+/// The name, without extension, of the files a tour is written to: `<tour-id>.md` by `dump` and
+/// `tours/<tour-id>.tour` in a package.
+pub fn document_stem(tour_id: &str) -> String {
+    escape("", tour_id)
+}
+
+struct Known {
+    path: PathBuf,
+    stops: HashSet<String>,
+}
+
+/// The tours that `tour:` links can point at, by id.
+#[derive(Default)]
+pub struct Catalog {
+    tours: HashMap<String, Known>,
+    /// Whether `tour:` links resolve to the tour files bundled in a package rather than to the
+    /// dumped Markdown documents.
+    packaged: bool,
+}
+
+impl Catalog {
+    pub fn add(&mut self, path: &Path, tour: &Tour) {
+        self.tours.insert(
+            tour.id.clone(),
+            Known {
+                path: path.to_owned(),
+                stops: tour.stops.iter().map(|stop| stop.id.clone()).collect(),
+            },
+        );
+    }
+
+    /// Resolves `tour:` links to the tour files bundled in a package.
+    pub fn packaged(mut self) -> Self {
+        self.packaged = true;
+        self
+    }
+
+    /// The document `tour:` links to the tour `tour_id` point at.
+    pub fn document(&self, tour_id: &str) -> String {
+        if self.packaged {
+            format!("tours/{}.tour", document_stem(tour_id))
+        } else {
+            format!("{}.md", document_stem(tour_id))
+        }
+    }
+
+    /// The tours in the same directory as `tour_file`. Files that fail to parse are skipped.
+    pub fn siblings(tour_file: &Path) -> Result<Self> {
+        let mut catalog = Catalog::default();
+        let dir = match tour_file.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => Path::new("."),
+        };
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |e| e == "tour") {
+                if let Ok(tour) = read_tour(&path) {
+                    catalog.add(&path, &tour);
+                }
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// The URL `link`, found in `tour`, points at, or why it cannot be resolved.
+    pub fn resolve(&self, tour: &Tour, link: &StopLink<'_>) -> std::result::Result<String, String> {
+        let (document, stops) = match link.tour {
+            None => (None, None),
+            Some(id) if id == tour.id => (None, None),
+            Some(id) => match self.tours.get(id) {
+                Some(known) => (Some(self.document(id)), Some(&known.stops)),
+                None => return Err(format!("unknown tour '{}'", id)),
+            },
+        };
+        let stop = match link.stop {
+            Some(stop) => stop,
+            None => return Ok(document.unwrap_or_else(|| "#".to_owned())),
+        };
+        let exists = match stops {
+            Some(stops) => stops.contains(stop),
+            None => tour.stops.iter().any(|s| s.id == stop),
+        };
+        if !exists {
+            return Err(format!(
+                "unknown stop '{}' in tour '{}'",
+                stop,
+                link.tour.unwrap_or(&tour.id)
+            ));
+        }
+        Ok(format!(
+            "{}#{}",
+            document.unwrap_or_default(),
+            stop_anchor(stop)
+        ))
+    }
+
+    /// The URL the stop link `link`, found in `tour`, points at, or a message saying why it
+    /// cannot be resolved. `None` if `link` is not a stop link.
+    pub fn resolve_link(
+        &self,
+        tour: &Tour,
+        link: &Link<'_>,
+    ) -> Option<std::result::Result<String, String>> {
+        let target = match link.target {
+            Target::Inline(target) => target,
+            _ => return None,
+        };
+        // A bare `#<stop-id>` is taken as a stop link only when the tour has such a stop, so that
+        // links to headings in the same body are left alone.
+        if target.starts_with('#') {
+            let stop = StopLink {
+                tour: None,
+                stop: Some(&target[1..]),
+            };
+            return self.resolve(tour, &stop).ok().map(Ok);
+        }
+        let stop_link = StopLink::parse(target)?;
+        Some(
+            self.resolve(tour, &stop_link)
+                .map_err(|e| format!("The link [{}]({}) points to {}.", link.text, target, e)),
+        )
+    }
+
+    /// Replaces the target of every stop link in `body` with the URL it resolves to. Links that
+    /// do not resolve are left alone and described in the returned messages.
+    pub fn rewrite(&self, tour: &Tour, body: &str) -> (String, Vec<String>) {
+        let mut out = String::new();
+        let mut unresolved = vec![];
+        let mut last = 0;
+        for link in markdown::links(body) {
+            match self.resolve_link(tour, &link) {
+                Some(Ok(url)) => {
+                    out.push_str(&body[last..link.range.start]);
+                    out.push_str(&format!("[{}]({})", link.text, url));
+                    last = link.range.end;
+                }
+                Some(Err(message)) => unresolved.push(message),
+                None => {}
+            }
+        }
+        out.push_str(&body[last..]);
+        (out, unresolved)
+    }
+
+    /// The files of the other tours that `tour`'s description and stops link to, by tour id.
+    pub fn linked_tours<'a>(&'a self, tour: &'a Tour) -> BTreeMap<&'a str, &'a Path> {
+        let bodies = Some(&tour.description)
+            .into_iter()
+            .chain(tour.stops.iter().map(|stop| &stop.description));
+        let mut linked = BTreeMap::new();
+        for body in bodies {
+            for link in markdown::links(body) {
+                let id = match link.target {
+                    Target::Inline(target) => StopLink::parse(target).and_then(|l| l.tour),
+                    _ => None,
+                };
+                if let Some(id) = id.filter(|id| *id != tour.id) {
+                    if let Some(known) = self.tours.get(id) {
+                        linked.insert(id, known.path.as_path());
+                    }
+                }
+            }
+        }
+        linked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stop_anchor, Catalog};
+    use crate::serialize::parse_tour;
+    use std::path::Path;
+
+    fn tour(id: &str) -> crate::types::Tour {
+        parse_tour(&format!(
+            r#"{{
+                "protocolVersion": "1.0", "id": "{}", "title": "T", "description": "",
+                "stops": [{{ "id": "1", "title": "A", "body": "", "relPath": "a.rs",
+                             "repository": "a", "line": 1, "childStops": [] }}],
+                "repositories": [{{ "repository": "a", "commit": "abc" }}]
+            }}"#,
+            id
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn rewrites_stop_and_tour_links() {
+        let (intro, other) = (tour("intro"), tour("other"));
+        let mut catalog = Catalog::default();
+        catalog.add(Path::new("docs/other-tour.tour"), &other);
+        let (body, unresolved) = catalog.rewrite(
+            &intro,
+            "[a](stop:1), [b](tour:other#1), [c](tour:other), [d](stop:2), [e](tour:gone#1), \
+             [f](http://x), [g](#1), [h](#usage)",
+        );
+        assert_eq!(
+            body,
+            "[a](#stop-1), [b](other.md#stop-1), [c](other.md), [d](stop:2), \
+             [e](tour:gone#1), [f](http://x), [g](#stop-1), [h](#usage)"
+        );
+        assert_eq!(
+            unresolved,
+            vec![
+                "The link [d](stop:2) points to unknown stop '2' in tour 'intro'.",
+                "The link [e](tour:gone#1) points to unknown tour 'gone'.",
+            ]
+        );
+        assert_eq!(
+            catalog.packaged().rewrite(&intro, "[b](tour:other#1)").0,
+            "[b](tours/other.tour#stop-1)"
+        );
+    }
+
+    #[test]
+    fn anchors_are_safe_and_distinct() {
+        assert_eq!(stop_anchor("intro-1"), "stop-intro-1");
+        assert_eq!(stop_anchor("a b\"<"), "stop-a_20b_22_3C");
+        assert_ne!(stop_anchor("a_20b"), stop_anchor("a b"));
+    }
+
+    #[test]
+    fn finds_linked_tours() {
+        let (mut intro, other) = (tour("intro"), tour("other"));
+        intro.stops[0].description = "[b](tour:other#1), [c](tour:gone), [d](stop:1)".to_owned();
+        let mut catalog = Catalog::default();
+        catalog.add(Path::new("docs/other.tour"), &other);
+        catalog.add(Path::new("docs/intro.tour"), &intro);
+        assert_eq!(
+            catalog.linked_tours(&intro).into_iter().collect::<Vec<_>>(),
+            vec![("other", Path::new("docs/other.tour"))]
+        );
+    }
+}
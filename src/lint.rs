@@ -0,0 +1,345 @@
+use crate::links::Catalog;
+use crate::markdown::{self, Target};
+use crate::types::{Stop, Tour};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Bodies longer than this many characters are reported by `long-body` unless configured.
+pub const DEFAULT_MAX_BODY_LENGTH: usize = 2000;
+
+/// Marks rules to skip for one stop, e.g. `<!-- tourist-lint-allow: empty-body, same-line -->`.
+const ALLOW_MARKER: &str = "tourist-lint-allow:";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Off,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Off => write!(f, "off"),
+            Level::Warn => write!(f, "warning"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Every rule, with its default level.
+pub const RULES: &[(&str, Level)] = &[
+    ("empty-title", Level::Error),
+    ("duplicate-title", Level::Warn),
+    ("duplicate-stop-id", Level::Error),
+    ("empty-body", Level::Warn),
+    ("long-body", Level::Warn),
+    ("unused-repository", Level::Warn),
+    ("missing-commit", Level::Error),
+    ("same-line", Level::Warn),
+    ("broken-link", Level::Error),
+    ("broken-reference", Level::Error),
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub level: Level,
+    /// The id of the stop the finding is about, if it is about one.
+    pub stop: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]", self.level, self.rule)?;
+        if let Some(stop) = &self.stop {
+            write!(f, " stop {}", stop)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Which rules run, and at what level.
+pub struct Settings {
+    pub levels: BTreeMap<String, Level>,
+    pub max_body_length: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            levels: BTreeMap::new(),
+            max_body_length: DEFAULT_MAX_BODY_LENGTH,
+        }
+    }
+}
+
+impl Settings {
+    fn level(&self, rule: &str) -> Level {
+        self.levels.get(rule).cloned().unwrap_or_else(|| {
+            RULES
+                .iter()
+                .find(|(name, _)| *name == rule)
+                .map_or(Level::Off, |(_, level)| *level)
+        })
+    }
+
+    /// Configured rule names that no rule has.
+    pub fn unknown_rules(&self) -> Vec<&str> {
+        self.levels
+            .keys()
+            .map(String::as_str)
+            .filter(|name| RULES.iter().all(|(rule, _)| rule != name))
+            .collect()
+    }
+}
+
+/// The rules a stop's body turns off for that stop.
+fn allowed(stop: &Stop) -> HashSet<&str> {
+    stop.description
+        .match_indices(ALLOW_MARKER)
+        .flat_map(|(i, _)| {
+            let rest = &stop.description[i + ALLOW_MARKER.len()..];
+            let end = rest.find("-->").unwrap_or(rest.len());
+            rest[..end].split(',').map(str::trim)
+        })
+        .collect()
+}
+
+struct Linter<'a> {
+    settings: &'a Settings,
+    catalog: &'a Catalog,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, rule: &'static str, stop: Option<&Stop>, message: String) {
+        let level = self.settings.level(rule);
+        let allowed = match stop {
+            Some(stop) => allowed(stop).contains(rule),
+            None => false,
+        };
+        if level == Level::Off || allowed {
+            return;
+        }
+        self.findings.push(Finding {
+            rule,
+            level,
+            stop: stop.map(|stop| stop.id.clone()),
+            message,
+        });
+    }
+
+    fn check_body(&mut self, tour: &Tour, stop: &Stop) {
+        let body = &stop.description;
+        if body.trim().is_empty() {
+            self.report("empty-body", Some(stop), "The body is empty.".to_owned());
+        }
+        let length = body.chars().count();
+        if length > self.settings.max_body_length {
+            self.report(
+                "long-body",
+                Some(stop),
+                format!(
+                    "The body is {} characters long; the limit is {}.",
+                    length, self.settings.max_body_length
+                ),
+            );
+        }
+        let definitions = markdown::definitions(body);
+        for link in markdown::links(body) {
+            match link.target {
+                Target::Unclosed => self.report(
+                    "broken-link",
+                    Some(stop),
+                    format!(
+                        "The link [{}] is missing its closing parenthesis.",
+                        link.text
+                    ),
+                ),
+                Target::Inline("") => self.report(
+                    "broken-link",
+                    Some(stop),
+                    format!("The link [{}] has no target.", link.text),
+                ),
+                Target::Inline(_) => {
+                    if let Some(Err(message)) = self.catalog.resolve_link(tour, &link) {
+                        self.report("broken-reference", Some(stop), message);
+                    }
+                }
+                Target::Reference(label) => {
+                    if !definitions.contains(&label.to_lowercase()) {
+                        self.report(
+                            "broken-link",
+                            Some(stop),
+                            format!("The link [{}] uses undefined label '{}'.", link.text, label),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Checks `tour` against every rule that `settings` leaves on. Links to other tours are checked
+/// against `catalog`.
+pub fn lint(tour: &Tour, settings: &Settings, catalog: &Catalog) -> Vec<Finding> {
+    let mut linter = Linter {
+        settings,
+        catalog,
+        findings: vec![],
+    };
+    if tour.title.trim().is_empty() {
+        linter.report("empty-title", None, "The tour has no title.".to_owned());
+    }
+
+    let mut ids = HashSet::new();
+    let mut titles = HashMap::new();
+    for (i, stop) in tour.stops.iter().enumerate() {
+        if !ids.insert(&stop.id) {
+            linter.report(
+                "duplicate-stop-id",
+                Some(stop),
+                format!("Stop id '{}' is used more than once.", stop.id),
+            );
+        }
+        if stop.title.trim().is_empty() {
+            linter.report(
+                "empty-title",
+                Some(stop),
+                "The stop has no title.".to_owned(),
+            );
+        } else if let Some(first) = titles.insert(stop.title.trim(), &stop.id) {
+            linter.report(
+                "duplicate-title",
+                Some(stop),
+                format!("The title '{}' is also used by stop {}.", stop.title, first),
+            );
+        }
+        if !tour.repositories.contains_key(&stop.repository) {
+            linter.report(
+                "missing-commit",
+                Some(stop),
+                format!(
+                    "Repository '{}' has no commit in the tour.",
+                    stop.repository
+                ),
+            );
+        }
+        if let Some(previous) = i.checked_sub(1).map(|j| &tour.stops[j]) {
+            if previous.repository == stop.repository
+                && previous.path == stop.path
+                && previous.line == stop.line
+            {
+                linter.report(
+                    "same-line",
+                    Some(stop),
+                    format!("The previous stop, {}, is on the same line.", previous.id),
+                );
+            }
+        }
+        linter.check_body(tour, stop);
+    }
+
+    let mut unused = tour
+        .repositories
+        .keys()
+        .filter(|repository| {
+            tour.stops
+                .iter()
+                .all(|stop| &stop.repository != *repository)
+        })
+        .collect::<Vec<_>>();
+    unused.sort();
+    for repository in unused {
+        linter.report(
+            "unused-repository",
+            None,
+            format!("Repository '{}' is not used by any stop.", repository),
+        );
+    }
+    linter.findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, Level, Settings};
+    use crate::links::Catalog;
+    use crate::serialize::parse_tour;
+
+    const TOUR: &str = r#"{
+        "protocolVersion": "1.0",
+        "id": "tour",
+        "title": "Tour",
+        "description": "",
+        "stops": [
+            {
+                "id": "1", "title": "A", "body": "See [next](#2), [gone](stop:9), [usage](#usage) and [away](tour:other#1).",
+                "relPath": "a.rs", "repository": "a", "line": 3, "childStops": []
+            },
+            {
+                "id": "2", "title": "A", "body": "<!-- tourist-lint-allow: same-line -->",
+                "relPath": "a.rs", "repository": "a", "line": 3, "childStops": []
+            },
+            {
+                "id": "3", "title": "C", "body": "[x](",
+                "relPath": "b.rs", "repository": "b", "line": 1, "childStops": []
+            }
+        ],
+        "repositories": [
+            { "repository": "a", "commit": "abc" },
+            { "repository": "unused", "commit": "def" }
+        ]
+    }"#;
+
+    fn rules(settings: &Settings) -> Vec<(&'static str, Option<String>)> {
+        lint(&parse_tour(TOUR).unwrap(), settings, &Catalog::default())
+            .into_iter()
+            .map(|finding| (finding.rule, finding.stop))
+            .collect()
+    }
+
+    #[test]
+    fn reports_each_rule_and_honours_suppression() {
+        let stop = |id: &str| Some(id.to_owned());
+        assert_eq!(
+            rules(&Settings::default()),
+            vec![
+                ("broken-reference", stop("1")),
+                ("broken-reference", stop("1")),
+                ("duplicate-title", stop("2")),
+                ("missing-commit", stop("3")),
+                ("broken-link", stop("3")),
+                ("unused-repository", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn levels_are_configurable() {
+        let mut settings = Settings::default();
+        settings.levels.insert("broken-link".to_owned(), Level::Off);
+        settings
+            .levels
+            .insert("unused-repository".to_owned(), Level::Off);
+        settings
+            .levels
+            .insert("no-such-rule".to_owned(), Level::Warn);
+        settings.max_body_length = 10;
+        assert_eq!(
+            rules(&settings),
+            vec![
+                ("long-body", Some("1".to_owned())),
+                ("broken-reference", Some("1".to_owned())),
+                ("broken-reference", Some("1".to_owned())),
+                ("duplicate-title", Some("2".to_owned())),
+                ("long-body", Some("2".to_owned())),
+                ("missing-commit", Some("3".to_owned())),
+            ]
+        );
+        assert_eq!(settings.unknown_rules(), vec!["no-such-rule"]);
+    }
+}
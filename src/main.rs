@@ -1,59 +1,64 @@
-use dirs;
-use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use structopt::StructOpt;
 
 mod command;
+mod config;
+mod diagnostic;
 mod error;
+mod links;
+mod lint;
+mod markdown;
+mod relocate;
 mod serialize;
 mod types;
 mod vcs;
+mod workspace;
 
-use command::{Dump, Package};
-use error::Result;
-use serialize::parse_tour;
+use command::{
+    Affected, Anchor, Check, CheckTarget, Dump, DumpFormat, HookInstall, Lint, Open, Package, Pin,
+    PinRefs, PostCommit, PreCommit, Recover, Refresh, Status, Target, UnpinRefs, Validate,
+    WorkspaceSync,
+};
+use config::Config;
+use diagnostic::MessageFormat;
+use error::{Error, Result};
+use links::Catalog;
+use relocate::Mode;
+use serialize::{parse_tour, read_tour, serialize_tour};
 use types::path::AbsolutePathBuf;
-use types::Index;
-use vcs::Git;
+use types::{Index, Tour};
+use vcs::{is_pinned, Git};
+use workspace::Workspace;
 
-fn get_default_config() -> Option<PathBuf> {
-    dirs::home_dir().and_then(|mut path| {
-        path.push(".tourist");
-        if path.exists() {
-            Some(path)
-        } else {
-            None
-        }
-    })
-}
-
-fn get_override_config() -> Option<PathBuf> {
-    env::var("TOURIST_CONFIG").ok().and_then(|val| {
-        let path = PathBuf::from(val);
-        if path.exists() {
-            Some(path)
-        } else {
-            None
-        }
-    })
+fn parse_assignment(s: &str) -> std::result::Result<(String, String), String> {
+    match s.find('=') {
+        Some(i) if i > 0 => Ok((s[..i].to_owned(), s[i + 1..].to_owned())),
+        _ => Err(format!("expected NAME=VALUE, got \"{}\"", s)),
+    }
 }
 
-fn get_index() -> Result<Index> {
-    let path = get_override_config().or_else(get_default_config);
-    Ok(match path {
-        None => HashMap::new(),
-        Some(path) => {
-            let contents = &fs::read_to_string(path)?;
-            let index: HashMap<String, PathBuf> = serde_json::from_str(contents)?;
-            index
-                .iter()
-                .filter_map(|(k, v)| AbsolutePathBuf::new(v.clone()).map(|ap| (k.to_owned(), ap)))
-                .collect::<HashMap<_, _>>()
-        }
-    })
+#[derive(StructOpt)]
+struct RepoArgs {
+    #[structopt(
+        long = "repo",
+        value_name = "NAME=PATH",
+        number_of_values = 1,
+        help = "Use the repository at PATH for NAME, ignoring the index. Takes NAME=PATH; may be \
+                repeated.",
+        parse(try_from_str = "parse_assignment")
+    )]
+    repos: Vec<(String, String)>,
+    #[structopt(
+        long = "rename-repo",
+        value_name = "OLD=NEW",
+        number_of_values = 1,
+        help = "Treat the tour's repository OLD as NEW. Takes OLD=NEW; may be repeated.",
+        parse(try_from_str = "parse_assignment")
+    )]
+    renames: Vec<(String, String)>,
 }
 
 #[derive(StructOpt)]
@@ -64,12 +69,43 @@ struct DumpArgs {
         help = "Look in git for the source code referenced by each stop."
     )]
     context: bool,
+    #[structopt(
+        long = "no-context",
+        help = "Leave out the source code, even if the config asks for it.",
+        conflicts_with = "context"
+    )]
+    no_context: bool,
     #[structopt(short = "A", help = "Lines to be shown above the target line.")]
     above: Option<usize>,
     #[structopt(short = "B", help = "Lines to be shown below the target line.")]
     below: Option<usize>,
     #[structopt(short = "C", help = "Lines to be shown around the target line.")]
     around: Option<usize>,
+    #[structopt(
+        long = "tab-width",
+        help = "Expand tabs in source code to this many columns."
+    )]
+    tab_width: Option<usize>,
+    #[structopt(
+        long = "keep-going",
+        help = "Carry on past stops that fail, and report every failure at the end."
+    )]
+    keep_going: bool,
+    #[structopt(
+        long = "format",
+        help = "What to write the tour as.",
+        raw(possible_values = r#"&["markdown", "json"]"#)
+    )]
+    format: Option<DumpFormat>,
+    #[structopt(
+        long = "out-dir",
+        help = "Write the document to <tour id>.md (or .json) in this directory, the name that \
+                links from other tours point at, instead of printing it.",
+        parse(from_os_str)
+    )]
+    out_dir: Option<PathBuf>,
+    #[structopt(flatten)]
+    repo_args: RepoArgs,
     #[structopt(name = "TOURFILE", parse(from_os_str))]
     tour_file: PathBuf,
 }
@@ -84,58 +120,677 @@ struct PackageArgs {
         parse(from_os_str)
     )]
     out: Option<PathBuf>,
+    #[structopt(
+        long = "keep-going",
+        help = "Carry on past stops that fail, and report every failure at the end."
+    )]
+    keep_going: bool,
+    #[structopt(flatten)]
+    repo_args: RepoArgs,
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct PinArgs {
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct AnchorArgs {
+    #[structopt(
+        long = "text",
+        help = "Store each stop's line text in the anchor, not just its hash."
+    )]
+    text: bool,
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct CheckArgs {
+    #[structopt(
+        long = "target",
+        help = "The revision to check the stops against. Defaults to HEAD."
+    )]
+    target: Option<String>,
+    #[structopt(
+        long = "worktree",
+        help = "Check the stops against the working tree instead of a revision.",
+        conflicts_with = "target"
+    )]
+    worktree: bool,
     #[structopt(name = "TOURFILE", parse(from_os_str))]
     tour_file: PathBuf,
 }
 
+#[derive(StructOpt)]
+struct RefreshArgs {
+    #[structopt(
+        long = "to",
+        default_value = "HEAD",
+        help = "The revision to move the tour to."
+    )]
+    to: String,
+    #[structopt(
+        long = "walk-history",
+        help = "Follow each stop through every commit on the first-parent history, rather than \
+                diffing the two commits directly. Slower, but more accurate across many commits."
+    )]
+    walk_history: bool,
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct OpenArgs {
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+    #[structopt(name = "STOP")]
+    stop: String,
+}
+
+#[derive(StructOpt)]
+struct RecoverArgs {
+    #[structopt(
+        long = "apply",
+        help = "Write the proposed replacements to the tour file."
+    )]
+    apply: bool,
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct RefsArgs {
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct StatusArgs {
+    #[structopt(
+        long = "worktree",
+        help = "Compare against the working tree, including uncommitted changes, rather than HEAD."
+    )]
+    worktree: bool,
+    #[structopt(
+        long = "porcelain",
+        help = "Print one tab-separated line per stop, in a format that will stay stable."
+    )]
+    porcelain: bool,
+    #[structopt(name = "TOURFILE", parse(from_os_str))]
+    tour_file: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct AffectedArgs {
+    #[structopt(
+        long = "json",
+        help = "Print the affected stops as a JSON array, for use in CI."
+    )]
+    json: bool,
+    #[structopt(
+        name = "REPOSITORY",
+        help = "The indexed repository the commits belong to."
+    )]
+    repository: String,
+    #[structopt(
+        name = "RANGE",
+        help = "The commits to check, as `base..head` or a single commit."
+    )]
+    range: String,
+    #[structopt(name = "TOURFILE", parse(from_os_str), required = true)]
+    tour_files: Vec<PathBuf>,
+}
+
+#[derive(StructOpt)]
+struct HookInstallArgs {
+    #[structopt(
+        long = "block",
+        help = "Refuse commits that delete lines stops point at, rather than only warning."
+    )]
+    block: bool,
+    #[structopt(
+        long = "refresh",
+        help = "Also install a post-commit hook that refreshes the repository's tours."
+    )]
+    refresh: bool,
+    #[structopt(name = "REPOSITORY")]
+    repository: String,
+}
+
+#[derive(StructOpt)]
+struct PreCommitArgs {
+    #[structopt(long = "block", help = "Fail if a stop's line is deleted.")]
+    block: bool,
+    #[structopt(name = "REPOSITORY")]
+    repository: String,
+}
+
+#[derive(StructOpt)]
+struct PostCommitArgs {
+    #[structopt(name = "REPOSITORY")]
+    repository: String,
+}
+
+#[derive(StructOpt)]
+enum HookArgs {
+    #[structopt(
+        name = "install",
+        about = "Install git hooks that keep the tours in an indexed repository up to date."
+    )]
+    Install(HookInstallArgs),
+    #[structopt(
+        name = "pre-commit",
+        about = "Check the changes about to be committed against the repository's tours."
+    )]
+    PreCommit(PreCommitArgs),
+    #[structopt(
+        name = "post-commit",
+        about = "Refresh the repository's tours to the commit just made."
+    )]
+    PostCommit(PostCommitArgs),
+}
+
+#[derive(StructOpt)]
+struct LintArgs {
+    #[structopt(long = "deny-warnings", help = "Fail on warnings as well as errors.")]
+    deny_warnings: bool,
+    #[structopt(name = "TOURFILE", parse(from_os_str), required = true)]
+    tour_files: Vec<PathBuf>,
+}
+
+#[derive(StructOpt)]
+struct ValidateArgs {
+    #[structopt(
+        long = "strict",
+        help = "Also report fields that the tour file format does not define."
+    )]
+    strict: bool,
+    #[structopt(name = "TOURFILE", parse(from_os_str), required = true)]
+    tour_files: Vec<PathBuf>,
+}
+
+#[derive(StructOpt)]
+enum ConfigArgs {
+    #[structopt(
+        name = "migrate",
+        about = "Rewrite config files holding only a repository map in the current format."
+    )]
+    Migrate,
+    #[structopt(
+        name = "show",
+        about = "Show the effective config, and the file each value comes from."
+    )]
+    Show(ConfigShowArgs),
+}
+
+#[derive(StructOpt)]
+enum WorkspaceArgs {
+    #[structopt(
+        name = "sync",
+        about = "Clone the workspace's missing repositories from their mirrors."
+    )]
+    Sync,
+}
+
+#[derive(StructOpt)]
+struct ConfigShowArgs {
+    #[structopt(
+        name = "TOURFILE",
+        parse(from_os_str),
+        help = "Show the config that applies to this tour file, including its project config."
+    )]
+    tour_file: Option<PathBuf>,
+}
+
 #[derive(StructOpt)]
 #[structopt(
     name = "tourist",
     about = "A CLI tool for the tourist documentation system."
 )]
+struct Opts {
+    #[structopt(
+        long = "message-format",
+        default_value = "human",
+        raw(possible_values = r#"&["human", "json"]"#),
+        help = "How to print errors and warnings. `json` prints one object per line, with a \
+                stable `code`."
+    )]
+    message_format: MessageFormat,
+    #[structopt(subcommand)]
+    command: TouristArgs,
+}
+
+#[derive(StructOpt)]
 enum TouristArgs {
-    #[structopt(name = "dump", about = "Dump a .tour file as a markdown document.")]
+    #[structopt(
+        name = "dump",
+        about = "Dump a .tour file as a Markdown or JSON document."
+    )]
     Dump(DumpArgs),
     #[structopt(
         name = "package",
         about = "Package a tour file for viewing on the web."
     )]
     Package(PackageArgs),
+    #[structopt(
+        name = "pin",
+        about = "Resolve the branches, tags and short commit ids in a tour file to full commit ids."
+    )]
+    Pin(PinArgs),
+    #[structopt(
+        name = "anchor",
+        about = "Record the content each stop points at, so it can be found again later."
+    )]
+    Anchor(AnchorArgs),
+    #[structopt(
+        name = "check",
+        about = "Check that each anchored stop still points at its anchored content."
+    )]
+    Check(CheckArgs),
+    #[structopt(
+        name = "refresh",
+        about = "Move a tour to a newer commit, relocating each stop along the way."
+    )]
+    Refresh(RefreshArgs),
+    #[structopt(
+        name = "open",
+        about = "Open the file a stop points at in an editor, at the stop's line. The editor is \
+                 set by `editor.command` in the config file."
+    )]
+    Open(OpenArgs),
+    #[structopt(
+        name = "recover",
+        about = "Find replacements for tour commits lost to a rebase or force-push."
+    )]
+    Recover(RecoverArgs),
+    #[structopt(
+        name = "pin-refs",
+        about = "Create refs/tourist/ refs that keep a tour's commits from being garbage collected."
+    )]
+    PinRefs(RefsArgs),
+    #[structopt(
+        name = "unpin-refs",
+        about = "Delete the refs/tourist/ refs created for a tour by pin-refs."
+    )]
+    UnpinRefs(RefsArgs),
+    #[structopt(
+        name = "status",
+        about = "Show which stops have changed between the tour's commits and HEAD or the worktree."
+    )]
+    Status(StatusArgs),
+    #[structopt(
+        name = "affected",
+        about = "List the stops in a set of tours whose code is changed by a range of commits."
+    )]
+    Affected(AffectedArgs),
+    #[structopt(name = "hook", about = "Manage and run tourist's git hooks.")]
+    Hook(HookArgs),
+    #[structopt(
+        name = "validate",
+        about = "Check tour files for problems, reporting each with its line and column."
+    )]
+    Validate(ValidateArgs),
+    #[structopt(
+        name = "lint",
+        about = "Check tour files for common authoring mistakes. Rules are configured under \
+                 `lint` in the config file, and can be turned off for one stop with \
+                 `<!-- tourist-lint-allow: RULE, ... -->` in its body."
+    )]
+    Lint(LintArgs),
+    #[structopt(name = "config", about = "Manage tourist's configuration file.")]
+    Config(ConfigArgs),
+    #[structopt(
+        name = "workspace",
+        about = "Manage the workspace described by the nearest tourist.workspace.json."
+    )]
+    Workspace(WorkspaceArgs),
+}
+
+fn warn_unpinned(tour: &Tour) {
+    for (repository, commit) in &tour.repositories {
+        if !is_pinned(commit) {
+            diagnostic::warning(
+                "unpinned-reference",
+                &format!(
+                    "repository '{}' uses the unpinned reference '{}'; run `tourist pin` to fix \
+                     it to a commit.",
+                    repository, commit
+                ),
+            );
+        }
+    }
+}
+
+/// Applies the `--rename-repo` remappings to `tour`, then the `--repo` overrides to `index`.
+fn apply_repo_args(args: &RepoArgs, tour: &mut Tour, index: &mut Index) -> Result<()> {
+    for from in tour.rename_repositories(&args.renames)? {
+        diagnostic::warning(
+            "unused-rename",
+            &format!("The tour has no repository named {}.", from),
+        );
+    }
+    let cwd = env::current_dir()?;
+    for (name, path) in &args.repos {
+        let path = cwd.join(path);
+        if !path.is_dir() {
+            return Err(Error::RepoNotADirectory(name.clone(), path));
+        }
+        if let Some(path) = AbsolutePathBuf::new(path) {
+            index.insert(name.clone(), path);
+        }
+    }
+    Ok(())
+}
+
+/// Where config discovery starts: the directory of the tour file, or the current directory.
+fn config_start(tour_file: Option<&Path>) -> Result<PathBuf> {
+    let cwd = env::current_dir()?;
+    Ok(match tour_file.and_then(Path::parent) {
+        Some(dir) => cwd.join(dir),
+        None => cwd,
+    })
+}
+
+impl TouristArgs {
+    fn tour_file(&self) -> Option<&Path> {
+        match self {
+            TouristArgs::Dump(args) => Some(&args.tour_file),
+            TouristArgs::Package(args) => Some(&args.tour_file),
+            TouristArgs::Pin(args) => Some(&args.tour_file),
+            TouristArgs::Anchor(args) => Some(&args.tour_file),
+            TouristArgs::Check(args) => Some(&args.tour_file),
+            TouristArgs::Refresh(args) => Some(&args.tour_file),
+            TouristArgs::Open(args) => Some(&args.tour_file),
+            TouristArgs::Recover(args) => Some(&args.tour_file),
+            TouristArgs::PinRefs(args) | TouristArgs::UnpinRefs(args) => Some(&args.tour_file),
+            TouristArgs::Status(args) => Some(&args.tour_file),
+            TouristArgs::Affected(args) => args.tour_files.first().map(PathBuf::as_path),
+            TouristArgs::Lint(args) => args.tour_files.first().map(PathBuf::as_path),
+            _ => None,
+        }
+    }
+}
+
+fn run_config(args: ConfigArgs) -> Result<()> {
+    match args {
+        ConfigArgs::Migrate => {
+            let paths = config::layer_paths(&config_start(None)?);
+            if paths.is_empty() {
+                println!("No config file to migrate.");
+            }
+            for path in paths {
+                if config::migrate(&path)? {
+                    println!(
+                        "Migrated {}; the original is saved as {}.",
+                        path.display(),
+                        path.with_extension("bak").display()
+                    );
+                } else {
+                    println!("{} is already up to date.", path.display());
+                }
+            }
+        }
+        ConfigArgs::Show(args) => {
+            let layers = config::layers(&config_start(
+                args.tour_file.as_ref().map(PathBuf::as_path),
+            )?)?;
+            println!("Config files, from lowest to highest precedence:");
+            for layer in &layers {
+                println!("  {}", layer.path.display());
+            }
+            println!();
+            for (key, (value, source)) in config::merge(&layers).sources {
+                println!("{} = {}  ({})", key, value, source.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The git backend, with rename and copy detection as configured.
+fn git(config: &Config) -> Git {
+    Git::new().with_similarity(config.diff.similarity())
 }
 
 fn run(opts: TouristArgs) -> Result<()> {
+    // Only the commands that use the config read it, so a broken config file cannot get in the
+    // way of the others.
+    let start = config_start(opts.tour_file())?;
+    let load_config = || config::load(&start);
     match opts {
+        TouristArgs::Config(args) => run_config(args)?,
+        TouristArgs::Workspace(WorkspaceArgs::Sync) => {
+            let config = load_config()?;
+            match Workspace::find(&start)? {
+                Some((_, workspace)) => WorkspaceSync::new(git(&config)).process(&workspace)?,
+                None => return Err(Error::NoWorkspace(start)),
+            }
+        }
         TouristArgs::Dump(args) => {
-            let tour = parse_tour(&fs::read_to_string(args.tour_file)?)?;
-            if args.context {
+            let config = load_config()?;
+            let mut tour = read_tour(&args.tour_file)?;
+            let mut index = config.index();
+            apply_repo_args(&args.repo_args, &mut tour, &mut index)?;
+            warn_unpinned(&tour);
+            let defaults = &config.dump;
+            let keep_going = args.keep_going || defaults.keep_going.unwrap_or(false);
+            let context = args.context || (!args.no_context && defaults.context.unwrap_or(false));
+            if context {
                 Dump::with_context(
-                    Git,
-                    get_index()?,
-                    args.around.or(args.above).unwrap_or(0),
-                    args.around.or(args.below).unwrap_or(0),
+                    git(&config),
+                    index,
+                    args.around.or(args.above).or(defaults.above).unwrap_or(0),
+                    args.around.or(args.below).or(defaults.below).unwrap_or(0),
+                    args.tab_width.or(defaults.tab_width),
+                    keep_going,
                 )
             } else {
-                Dump::new()
+                Dump::new(keep_going)
             }
-            .process(&tour)?;
+            .process(
+                &tour,
+                &Catalog::siblings(&args.tour_file)?,
+                args.format
+                    .or(defaults.format)
+                    .unwrap_or(DumpFormat::Markdown),
+                args.out_dir.as_ref().map(PathBuf::as_path),
+            )?;
         }
         TouristArgs::Package(args) => {
-            let tour_source = fs::read_to_string(args.tour_file)?;
-            let tour = parse_tour(&tour_source)?;
-            Package::new(Git, get_index()?).process(
-                &args.out.unwrap_or_else(|| PathBuf::from("out.tour.pkg")),
+            let config = load_config()?;
+            let mut tour_source = fs::read_to_string(&args.tour_file)?;
+            let mut tour = parse_tour(&tour_source).map_err(|e| e.in_file(&args.tour_file))?;
+            let mut index = config.index();
+            apply_repo_args(&args.repo_args, &mut tour, &mut index)?;
+            if !args.repo_args.renames.is_empty() {
+                // The packaged tour file must use the same names as the packaged sources.
+                tour_source = serialize_tour(tour)?;
+                tour = parse_tour(&tour_source)?;
+            }
+            warn_unpinned(&tour);
+            let defaults = &config.package;
+            let keep_going = args.keep_going || defaults.keep_going.unwrap_or(false);
+            Package::new(git(&config), index, keep_going).process(
+                &args
+                    .out
+                    .or_else(|| defaults.out.clone())
+                    .unwrap_or_else(|| PathBuf::from("out.tour.pkg")),
                 tour,
                 &tour_source,
+                Catalog::siblings(&args.tour_file)?,
             )?;
         }
+        TouristArgs::Open(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            Open::new(config.index(), config.editor.command.clone()).process(&tour, &args.stop)?;
+        }
+        TouristArgs::Pin(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            Pin::new(git(&config), config.index()).process(&args.tour_file, tour)?;
+        }
+        TouristArgs::Anchor(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            warn_unpinned(&tour);
+            Anchor::new(git(&config), config.index(), args.text).process(&args.tour_file, tour)?;
+        }
+        TouristArgs::Check(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            warn_unpinned(&tour);
+            let target = match args.target {
+                _ if args.worktree => CheckTarget::Worktree,
+                Some(revision) => CheckTarget::Revision(revision),
+                None => CheckTarget::Revision("HEAD".to_owned()),
+            };
+            Check::new(git(&config), config.index(), target).process(&tour)?;
+        }
+        TouristArgs::Refresh(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            let mode = if args.walk_history {
+                Mode::History
+            } else {
+                Mode::Direct
+            };
+            Refresh::new(git(&config), config.index(), mode).process(
+                &args.tour_file,
+                tour,
+                &args.to,
+            )?;
+        }
+        TouristArgs::Recover(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            Recover::new(git(&config), config.index(), args.apply)
+                .process(&args.tour_file, tour)?;
+        }
+        TouristArgs::PinRefs(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            warn_unpinned(&tour);
+            PinRefs::new(git(&config), config.index()).process(&tour)?;
+        }
+        TouristArgs::UnpinRefs(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            UnpinRefs::new(git(&config), config.index()).process(&tour)?;
+        }
+        TouristArgs::Status(args) => {
+            let config = load_config()?;
+            let tour = read_tour(&args.tour_file)?;
+            let target = if args.worktree {
+                Target::Worktree
+            } else {
+                Target::Head
+            };
+            Status::new(git(&config), config.index(), target, args.porcelain).process(&tour)?;
+        }
+        TouristArgs::Affected(args) => {
+            let config = load_config()?;
+            Affected::new(git(&config), config.index(), args.json).process(
+                &args.repository,
+                &args.range,
+                &args.tour_files,
+            )?;
+        }
+        TouristArgs::Validate(args) => {
+            Validate::new(args.strict).process(&args.tour_files)?;
+        }
+        TouristArgs::Lint(args) => {
+            let config = load_config()?;
+            Lint::new(config.lint.settings(), args.deny_warnings).process(&args.tour_files)?;
+        }
+        TouristArgs::Hook(HookArgs::Install(args)) => {
+            let config = load_config()?;
+            HookInstall::new(git(&config), config.index()).process(
+                &args.repository,
+                args.block,
+                args.refresh,
+            )?;
+        }
+        TouristArgs::Hook(HookArgs::PreCommit(args)) => {
+            let config = load_config()?;
+            PreCommit::new(git(&config), config.index(), args.block).process(&args.repository)?;
+        }
+        TouristArgs::Hook(HookArgs::PostCommit(args)) => {
+            let config = load_config()?;
+            PostCommit::new(git(&config), config.index()).process(&args.repository)?;
+        }
     }
 
     Ok(())
 }
 
 fn main() {
-    if let Err(e) = run(TouristArgs::from_args()) {
-        eprintln!("{}", e);
-        process::exit(1);
+    let opts = Opts::from_args();
+    diagnostic::set_format(opts.message_format);
+    if let Err(e) = run(opts.command) {
+        diagnostic::error(&e);
+        process::exit(e.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_repo_args, RepoArgs};
+    use crate::error::Error;
+    use crate::serialize::parse_tour;
+    use crate::types::Index;
+    use tempdir::TempDir;
+
+    #[test]
+    fn repo_args_rename_then_override() {
+        let mut tour = parse_tour(
+            r#"{
+                "protocolVersion": "1.0", "id": "t", "title": "T", "description": "",
+                "stops": [{ "id": "1", "title": "A", "body": "", "relPath": "a.rs",
+                            "repository": "old", "line": 1, "childStops": [] }],
+                "repositories": [{ "repository": "old", "commit": "abc" },
+                                 { "repository": "new", "commit": "def" }]
+            }"#,
+        )
+        .unwrap();
+        let dir = TempDir::new("repo").unwrap().into_path();
+        let pair = |a: &str, b: &str| (a.to_owned(), b.to_owned());
+
+        let mut index = Index::default();
+        let merge = RepoArgs {
+            repos: vec![],
+            renames: vec![pair("old", "new")],
+        };
+        match apply_repo_args(&merge, &mut tour, &mut index) {
+            Err(Error::RenameConflict(name)) => assert_eq!(name, "new"),
+            _ => panic!("expected a rename conflict"),
+        }
+
+        let missing = RepoArgs {
+            repos: vec![pair("old", &dir.join("missing").to_string_lossy())],
+            renames: vec![],
+        };
+        match apply_repo_args(&missing, &mut tour, &mut index) {
+            Err(e @ Error::RepoNotADirectory(_, _)) => {
+                assert_eq!(e.code(), "repo-not-a-directory")
+            }
+            _ => panic!("expected a missing directory"),
+        }
+
+        let swap = RepoArgs {
+            repos: vec![pair("old", &dir.to_string_lossy())],
+            renames: vec![pair("old", "new"), pair("new", "old")],
+        };
+        apply_repo_args(&swap, &mut tour, &mut index).unwrap();
+        assert_eq!(tour.stops[0].repository, "new");
+        assert_eq!(tour.repositories["old"], "def");
+        assert_eq!(
+            index.get("old").unwrap().as_absolute_path().as_path(),
+            dir.as_path()
+        );
     }
 }
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Where a Markdown link points.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Target<'a> {
+    /// `[text](target)`
+    Inline(&'a str),
+    /// `[text][label]`, or `[label][]`.
+    Reference(&'a str),
+    /// `[text](` with no closing parenthesis.
+    Unclosed,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Link<'a> {
+    pub text: &'a str,
+    pub target: Target<'a>,
+    /// The byte range of the whole link in the source.
+    pub range: Range<usize>,
+}
+
+/// Finds the position of the bracket closing the one at `open`, allowing nesting.
+fn closing(source: &str, open: usize, left: u8, right: u8) -> Option<usize> {
+    let mut depth = 0;
+    for (i, b) in source.bytes().enumerate().skip(open) {
+        if b == left {
+            depth += 1;
+        } else if b == right {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else if b == b'\n' && source[i + 1..].starts_with('\n') {
+            return None;
+        }
+    }
+    None
+}
+
+/// The lines of `source`, each with its line ending.
+fn lines_with_endings(source: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, _) in source.match_indices('\n') {
+        lines.push(&source[start..=i]);
+        start = i + 1;
+    }
+    if start < source.len() {
+        lines.push(&source[start..]);
+    }
+    lines
+}
+
+/// The byte ranges of `source` that are code, in which links are not recognized.
+fn code_spans(source: &str) -> Vec<Range<usize>> {
+    let mut spans = vec![];
+    let mut fence: Option<usize> = None;
+    let mut offset = 0;
+    for line in lines_with_endings(source) {
+        if line.trim_start().starts_with("```") {
+            match fence.take() {
+                Some(start) => spans.push(start..offset + line.len()),
+                None => fence = Some(offset),
+            }
+        } else if fence.is_none() {
+            let mut tick = None;
+            for (i, c) in line.char_indices() {
+                if c == '`' {
+                    match tick.take() {
+                        Some(start) => spans.push(offset + start..offset + i + 1),
+                        None => tick = Some(i),
+                    }
+                }
+            }
+        }
+        offset += line.len();
+    }
+    if let Some(start) = fence {
+        spans.push(start..source.len());
+    }
+    spans
+}
+
+/// Every inline and reference link in `source`, outside code.
+pub fn links(source: &str) -> Vec<Link<'_>> {
+    let code = code_spans(source);
+    let bytes = source.as_bytes();
+    let mut links = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'[' || code.iter().any(|span| span.contains(&i)) {
+            i += 1;
+            continue;
+        }
+        let close = match closing(source, i, b'[', b']') {
+            Some(close) => close,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let text = &source[i + 1..close];
+        let (target, end) = match bytes.get(close + 1) {
+            Some(b'(') => match closing(source, close + 1, b'(', b')') {
+                Some(end) => (Target::Inline(source[close + 2..end].trim()), end + 1),
+                None => (Target::Unclosed, close + 2),
+            },
+            Some(b'[') => match closing(source, close + 1, b'[', b']') {
+                Some(end) if end == close + 2 => (Target::Reference(text), end + 1),
+                Some(end) => (Target::Reference(&source[close + 2..end]), end + 1),
+                None => (Target::Unclosed, close + 2),
+            },
+            _ => {
+                i = close + 1;
+                continue;
+            }
+        };
+        links.push(Link {
+            text,
+            target,
+            range: i..end,
+        });
+        i = end;
+    }
+    links
+}
+
+/// The labels of the reference definitions (`[label]: url`) in `source`, lowercased.
+pub fn definitions(source: &str) -> HashSet<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            let close = line.find("]:")?;
+            if line.starts_with('[') {
+                Some(line[1..close].to_lowercase())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{definitions, links, Target};
+
+    #[test]
+    fn finds_links_outside_code() {
+        let source = "See [a](#1), [b][ref] and [c][].\n`[d](x)`\n```\n[e](y)\n```\n[f](oops\n\n\
+                      [ref]: http://example.com";
+        let found = links(source);
+        let targets = found.iter().map(|l| &l.target).collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            vec![
+                &Target::Inline("#1"),
+                &Target::Reference("ref"),
+                &Target::Reference("c"),
+                &Target::Unclosed,
+            ]
+        );
+        assert_eq!(&source[found[0].range.clone()], "[a](#1)");
+        assert!(definitions(source).contains("ref"));
+    }
+}
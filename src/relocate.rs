@@ -0,0 +1,174 @@
+use crate::error::Result;
+use crate::types::path::{AbsolutePath, RelativePathBuf};
+use crate::types::Anchor;
+use crate::vcs::{Changes, FileContents, LineFate, VCS};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Cross-file moves scoring below this are not trusted to carry a stop along.
+pub const MOVE_CONFIDENCE_THRESHOLD: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Diff the two commits against each other directly.
+    Direct,
+    /// Walk the first-parent history between the two commits, mapping the line through each one.
+    History,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub path: RelativePathBuf,
+    pub line: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Relocation {
+    Found(Location),
+    /// The line was deleted in `commit`. `nearest` is the closest surviving line at that commit.
+    Deleted {
+        commit: String,
+        nearest: Option<Location>,
+    },
+}
+
+pub struct Relocator<'a, V: VCS> {
+    vcs: &'a V,
+    mode: Mode,
+    diffs: RefCell<HashMap<(String, String), Rc<Changes>>>,
+}
+
+impl<'a, V: VCS> Relocator<'a, V> {
+    pub fn new(vcs: &'a V, mode: Mode) -> Self {
+        Relocator {
+            vcs,
+            mode,
+            diffs: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Finds where `location` at commit `from` lives at commit `to`. When the diff loses track
+    /// of the line, falls back to searching `to` for the stop's anchor, if it has one.
+    pub fn relocate(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        location: Location,
+        anchor: Option<&Anchor>,
+        from: &str,
+        to: &str,
+    ) -> Result<Relocation> {
+        let from = self.vcs.resolve_commit(repo_path, from)?;
+        let to = self.vcs.resolve_commit(repo_path, to)?;
+        let commits = match self.mode {
+            Mode::Direct => vec![from, to.clone()],
+            Mode::History => self.vcs.first_parent_history(repo_path, &from, &to)?,
+        };
+
+        let mut current = location;
+        for pair in commits.windows(2) {
+            let changes = self.changes(repo_path, &pair[0], &pair[1])?;
+            current = match changes.map_line(&current.path, current.line) {
+                LineFate::Unchanged => current,
+                LineFate::Moved { line } => Location {
+                    path: current.path,
+                    line,
+                },
+                LineFate::Renamed { path, line } => Location { path, line },
+                LineFate::Deleted {
+                    moved: Some(moved), ..
+                } if moved.confidence >= MOVE_CONFIDENCE_THRESHOLD => Location {
+                    path: moved.path,
+                    line: moved.line,
+                },
+                LineFate::Deleted { nearest, .. } => {
+                    if let Some(found) = self.find_anchor(repo_path, &current, anchor, &to)? {
+                        return Ok(Relocation::Found(found));
+                    }
+                    match self.find_in_copies(repo_path, &changes, &current, anchor, &pair[1])? {
+                        Some(found) => found,
+                        None => {
+                            return Ok(Relocation::Deleted {
+                                commit: pair[1].clone(),
+                                nearest: nearest.map(|(path, line)| Location { path, line }),
+                            })
+                        }
+                    }
+                }
+            };
+        }
+        Ok(Relocation::Found(current))
+    }
+
+    fn changes(&self, repo_path: AbsolutePath<'_>, from: &str, to: &str) -> Result<Rc<Changes>> {
+        let key = (from.to_owned(), to.to_owned());
+        if let Some(changes) = self.diffs.borrow().get(&key) {
+            return Ok(Rc::clone(changes));
+        }
+        let changes = Rc::new(self.vcs.diff_with_version(repo_path, from, to)?);
+        self.diffs.borrow_mut().insert(key, Rc::clone(&changes));
+        Ok(changes)
+    }
+
+    /// Looks for a line deleted from `location`'s file in the copies made of that file by the
+    /// same diff, matching the stop's anchor if it has one and the deleted text otherwise. The
+    /// match nearest the original line wins.
+    fn find_in_copies(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        changes: &Changes,
+        location: &Location,
+        anchor: Option<&Anchor>,
+        commit: &str,
+    ) -> Result<Option<Location>> {
+        let removed = changes
+            .removed_line(&location.path, location.line)
+            .map(str::trim);
+        for copy in changes.copies_of(&location.path) {
+            let code = match self.vcs.lookup_file_contents(repo_path, commit, copy) {
+                Ok(FileContents::Text(code)) => code,
+                _ => continue,
+            };
+            let lines = code.lines().collect::<Vec<_>>();
+            let found = match (anchor, removed) {
+                (Some(anchor), _) => anchor.find(&lines, location.line),
+                (None, Some(text)) if !text.is_empty() => (1..=lines.len())
+                    .filter(|l| lines[l - 1].trim() == text)
+                    .min_by_key(|l| (*l as isize - location.line as isize).abs()),
+                _ => None,
+            };
+            if let Some(line) = found {
+                return Ok(Some(Location {
+                    path: copy.clone(),
+                    line,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn find_anchor(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        location: &Location,
+        anchor: Option<&Anchor>,
+        commit: &str,
+    ) -> Result<Option<Location>> {
+        let anchor = match anchor {
+            Some(anchor) => anchor,
+            None => return Ok(None),
+        };
+        let code = match self
+            .vcs
+            .lookup_file_contents(repo_path, commit, &location.path)
+        {
+            Ok(FileContents::Text(code)) => code,
+            _ => return Ok(None),
+        };
+        let lines = code.lines().collect::<Vec<_>>();
+        Ok(anchor.find(&lines, location.line).map(|line| Location {
+            path: location.path.clone(),
+            line,
+        }))
+    }
+}
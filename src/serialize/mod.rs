@@ -1,9 +1,14 @@
+use crate::error::{Error, Result};
 use crate::types::Tour;
 use serde::Deserialize;
 use serde_json;
+use std::fs;
+use std::path::Path;
 
+mod validate;
 pub mod version1;
 
+pub use validate::{validate, Problem};
 pub use version1 as latest;
 
 #[derive(Deserialize, Debug)]
@@ -12,20 +17,86 @@ struct TfProtocol<'a> {
     protocol_version: &'a str,
 }
 
-pub fn parse_tour<'a>(s: &'a str) -> Result<Tour, serde_json::Error> {
-    let pv: TfProtocol<'a> = serde_json::from_str(s)?;
-    Ok(match pv.protocol_version {
-        version1::PROTOCOL_VERSION => serde_json::from_str::<version1::TourFile>(s)?.into(),
-        _ => panic!("Unexpected protocol version in tour file."),
-    })
+/// Parses a tour file. If it is malformed, the error lists every problem with its position.
+pub fn parse_tour<'a>(s: &'a str) -> Result<Tour> {
+    let parsed =
+        serde_json::from_str::<TfProtocol<'a>>(s).and_then(|pv| match pv.protocol_version {
+            version1::PROTOCOL_VERSION => Ok(Some(serde_json::from_str::<version1::TourFile>(s)?)),
+            _ => Ok(None),
+        });
+    let problems = match parsed {
+        Ok(Some(tour_file)) => return Ok(tour_file.into()),
+        Ok(None) => validate(s, false),
+        Err(e) => match validate(s, false) {
+            ref problems if problems.is_empty() => vec![validate::serde_problem(s, &e)],
+            problems => problems,
+        },
+    };
+    Err(Error::InvalidTour(None, problems))
+}
+
+/// Reads and parses the tour file at `path`.
+pub fn read_tour(path: &Path) -> Result<Tour> {
+    parse_tour(&fs::read_to_string(path)?).map_err(|e| e.in_file(path))
+}
+
+pub fn serialize_tour(tour: Tour) -> std::result::Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&latest::TourFile::from(tour))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::latest;
+    use super::{latest, parse_tour, serialize_tour};
 
     #[test]
     fn latest_is_correct() {
         assert_eq!(latest::PROTOCOL_VERSION, "1.0");
     }
+
+    #[test]
+    fn round_trip_keeps_pinned_refs() {
+        let source = r#"{
+            "protocolVersion": "1.0",
+            "id": "tour",
+            "title": "Tour",
+            "description": "",
+            "stops": [],
+            "repositories": [
+                { "repository": "a", "commit": "abc", "pinnedRef": "master" },
+                { "repository": "b", "commit": "def" }
+            ]
+        }"#;
+        let tour = parse_tour(&serialize_tour(parse_tour(source).unwrap()).unwrap()).unwrap();
+        assert_eq!(tour.repositories["a"], "abc");
+        assert_eq!(tour.pinned_refs["a"], "master");
+        assert!(!tour.pinned_refs.contains_key("b"));
+    }
+
+    #[test]
+    fn round_trip_keeps_anchors() {
+        let source = r#"{
+            "protocolVersion": "1.0",
+            "id": "tour",
+            "title": "Tour",
+            "description": "",
+            "stops": [
+                {
+                    "id": "1", "title": "A", "body": "", "relPath": "a.rs",
+                    "repository": "a", "line": 3, "childStops": [],
+                    "anchor": { "hash": "00000000000000ff", "before": ["01"], "after": [] }
+                },
+                {
+                    "id": "2", "title": "B", "body": "", "relPath": "b.rs",
+                    "repository": "a", "line": 1, "childStops": []
+                }
+            ],
+            "repositories": [{ "repository": "a", "commit": "abc" }]
+        }"#;
+        let tour = parse_tour(&serialize_tour(parse_tour(source).unwrap()).unwrap()).unwrap();
+        let anchor = tour.stops[0].anchor.as_ref().unwrap();
+        assert_eq!(anchor.hash, "00000000000000ff");
+        assert_eq!(anchor.before, vec!["01".to_owned()]);
+        assert_eq!(anchor.text, None);
+        assert!(tour.stops[1].anchor.is_none());
+    }
 }
@@ -0,0 +1,629 @@
+use super::version1::PROTOCOL_VERSION;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The kind of value a field of the tour format holds.
+#[derive(Debug)]
+enum Kind {
+    String,
+    Count,
+    Array(&'static Kind),
+    Object(&'static [Field]),
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::String => "a string",
+            Kind::Count => "a non-negative integer",
+            Kind::Array(_) => "an array",
+            Kind::Object(_) => "an object",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Field {
+    name: &'static str,
+    kind: Kind,
+    required: bool,
+}
+
+const fn field(name: &'static str, kind: Kind, required: bool) -> Field {
+    Field {
+        name,
+        kind,
+        required,
+    }
+}
+
+/// The fields of version 1 of the tour format, as `version1::TourFile` reads them.
+const TOUR_FILE: &[Field] = &[
+    field("protocolVersion", Kind::String, true),
+    field("id", Kind::String, true),
+    field("title", Kind::String, true),
+    field("description", Kind::String, true),
+    field("stops", Kind::Array(&Kind::Object(STOP)), true),
+    field("repositories", Kind::Array(&Kind::Object(REPOSITORY)), true),
+    field("generator", Kind::Count, false),
+];
+
+const STOP: &[Field] = &[
+    field("id", Kind::String, true),
+    field("title", Kind::String, true),
+    field("body", Kind::String, true),
+    field("relPath", Kind::String, true),
+    field("repository", Kind::String, true),
+    field("line", Kind::Count, true),
+    field("anchor", Kind::Object(ANCHOR), false),
+    field("blob", Kind::String, false),
+    field("childStops", Kind::Array(&Kind::Object(CHILD)), true),
+];
+
+const ANCHOR: &[Field] = &[
+    field("text", Kind::String, false),
+    field("hash", Kind::String, true),
+    field("before", Kind::Array(&Kind::String), false),
+    field("after", Kind::Array(&Kind::String), false),
+];
+
+const CHILD: &[Field] = &[
+    field("tourId", Kind::String, true),
+    field("stopNum", Kind::Count, true),
+];
+
+const REPOSITORY: &[Field] = &[
+    field("repository", Kind::String, true),
+    field("commit", Kind::String, true),
+    field("pinnedRef", Kind::String, false),
+    field("fingerprint", Kind::Object(FINGERPRINT), false),
+];
+
+const FINGERPRINT: &[Field] = &[
+    field("tree", Kind::String, true),
+    field("patch", Kind::String, true),
+];
+
+/// Something wrong with a tour file, at a 1-indexed line and column of its source.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// The source line the problem is on.
+    pub snippet: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}\n    | {}\n    | {}^",
+            self.line,
+            self.column,
+            self.message,
+            self.snippet,
+            " ".repeat(self.column.saturating_sub(1))
+        )
+    }
+}
+
+/// One step of the path to a value in a JSON document: an object member or an array element.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+fn child(path: &[Segment], segment: Segment) -> Vec<Segment> {
+    let mut child = path.to_vec();
+    child.push(segment);
+    child
+}
+
+fn key(name: &str) -> Segment {
+    Segment::Key(name.to_owned())
+}
+
+/// Records where each value of a syntactically valid JSON document starts, keyed by its path.
+/// Object members are located at their key.
+struct Locator {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    positions: HashMap<Vec<Segment>, (usize, usize)>,
+}
+
+impl Locator {
+    fn locate(source: &str) -> HashMap<Vec<Segment>, (usize, usize)> {
+        let mut locator = Locator {
+            chars: source.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+            positions: HashMap::new(),
+        };
+        locator.value(vec![]);
+        locator.positions
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn advance(&mut self) {
+        if let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.advance();
+        }
+    }
+
+    /// The four hex digits of a `\\u` escape, whose `u` has been read.
+    fn hex4(&mut self) -> u32 {
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code * 16 + self.peek().and_then(|c| c.to_digit(16)).unwrap_or(0);
+            self.advance();
+        }
+        code
+    }
+
+    /// The character a `\\u` escape stands for, joining a UTF-16 surrogate pair split over two
+    /// escapes.
+    fn unicode_escape(&mut self) -> char {
+        let high = self.hex4();
+        if (0xD800..0xDC00).contains(&high)
+            && self.peek() == Some('\\')
+            && self.chars.get(self.pos + 1) == Some(&'u')
+        {
+            self.advance();
+            self.advance();
+            let low = self.hex4();
+            if (0xDC00..0xE000).contains(&low) {
+                let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                return std::char::from_u32(code).unwrap_or('\u{FFFD}');
+            }
+            return '\u{FFFD}';
+        }
+        std::char::from_u32(high).unwrap_or('\u{FFFD}')
+    }
+
+    fn string(&mut self) -> String {
+        let mut s = String::new();
+        self.advance();
+        while let Some(c) = self.peek() {
+            self.advance();
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some(escaped) => escaped,
+                        None => break,
+                    };
+                    self.advance();
+                    s.push(match escaped {
+                        'u' => self.unicode_escape(),
+                        'b' => '\u{8}',
+                        'f' => '\u{C}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        other => other,
+                    });
+                }
+                c => s.push(c),
+            }
+        }
+        s
+    }
+
+    fn value(&mut self, path: Vec<Segment>) {
+        self.skip_whitespace();
+        let at = (self.line, self.column);
+        self.positions.entry(path.clone()).or_insert(at);
+        match self.peek() {
+            Some('{') => {
+                self.advance();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some('"') => {}
+                        Some(',') => {
+                            self.advance();
+                            continue;
+                        }
+                        _ => break,
+                    }
+                    let at = (self.line, self.column);
+                    let member = child(&path, Segment::Key(self.string()));
+                    self.positions.insert(member.clone(), at);
+                    self.skip_whitespace();
+                    self.advance();
+                    self.value(member);
+                }
+                self.advance();
+            }
+            Some('[') => {
+                self.advance();
+                let mut i = 0;
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(']') | None => break,
+                        Some(',') => self.advance(),
+                        _ => {
+                            self.value(child(&path, Segment::Index(i)));
+                            i += 1;
+                        }
+                    }
+                }
+                self.advance();
+            }
+            Some('"') => {
+                self.string();
+            }
+            _ => {
+                while self
+                    .peek()
+                    .map_or(false, |c| !c.is_whitespace() && !",]}".contains(c))
+                {
+                    self.advance();
+                }
+            }
+        }
+    }
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    positions: HashMap<Vec<Segment>, (usize, usize)>,
+    strict: bool,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Validator<'a> {
+    fn report_at(&mut self, line: usize, column: usize, message: String) {
+        let snippet = self
+            .source
+            .lines()
+            .nth(line.saturating_sub(1))
+            .unwrap_or("")
+            .to_owned();
+        self.problems.push(Problem {
+            line,
+            column,
+            message,
+            snippet,
+        });
+    }
+
+    /// Reports a problem at `path`, or at the nearest enclosing value that has a position.
+    fn report(&mut self, path: &[Segment], message: String) {
+        let mut path = path;
+        let (line, column) = loop {
+            if let Some(position) = self.positions.get(path) {
+                break *position;
+            }
+            match path.split_last() {
+                Some((_, parent)) => path = parent,
+                None => break (1, 1),
+            }
+        };
+        self.report_at(line, column, message);
+    }
+
+    fn check(&mut self, path: &[Segment], value: &Value, kind: &Kind) {
+        match (kind, value) {
+            (Kind::String, Value::String(_)) => {}
+            (Kind::Count, Value::Number(n)) if n.is_u64() => {}
+            (Kind::Array(inner), Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.check(&child(path, Segment::Index(i)), item, inner);
+                }
+            }
+            (Kind::Object(fields), Value::Object(members)) => {
+                self.check_object(path, members, fields)
+            }
+            (kind, value) => self.report(
+                path,
+                format!("expected {}, found {}", kind.name(), describe(value)),
+            ),
+        }
+    }
+
+    fn check_object(&mut self, path: &[Segment], members: &Map<String, Value>, fields: &[Field]) {
+        for field in fields {
+            match members.get(field.name) {
+                None if field.required => {
+                    self.report(path, format!("missing field `{}`", field.name))
+                }
+                None => {}
+                Some(Value::Null) if !field.required => {}
+                Some(value) => self.check(&child(path, key(field.name)), value, &field.kind),
+            }
+        }
+        if self.strict {
+            for name in members.keys() {
+                if !fields.iter().any(|f| f.name == name) {
+                    let message = format!("unknown field `{}`", name);
+                    self.report(&child(path, key(name)), message);
+                }
+            }
+        }
+    }
+
+    /// Checks that hold across the whole tour, once its shape is known to be right.
+    fn check_references(&mut self, tour: &Map<String, Value>) {
+        let field = |v: &Value, name: &str| v.get(name).and_then(Value::as_str).map(str::to_owned);
+        let empty = vec![];
+        let repositories = tour
+            .get("repositories")
+            .and_then(Value::as_array)
+            .unwrap_or(&empty);
+        let mut known = HashMap::new();
+        for (i, repository) in repositories.iter().enumerate() {
+            if let Some(name) = field(repository, "repository") {
+                if known.insert(name.clone(), i).is_some() {
+                    self.report(
+                        &[key("repositories"), Segment::Index(i), key("repository")],
+                        format!("repository `{}` is listed more than once", name),
+                    );
+                }
+            }
+        }
+
+        let stops = tour
+            .get("stops")
+            .and_then(Value::as_array)
+            .unwrap_or(&empty);
+        let mut ids = HashMap::new();
+        for (i, stop) in stops.iter().enumerate() {
+            if let Some(id) = field(stop, "id") {
+                if let Some(first) = ids.insert(id.clone(), i) {
+                    self.report(
+                        &[key("stops"), Segment::Index(i), key("id")],
+                        format!("duplicate stop id `{}`, also used by stops[{}]", id, first),
+                    );
+                }
+            }
+            if let Some(repository) = field(stop, "repository") {
+                if !known.contains_key(&repository) {
+                    self.report(
+                        &[key("stops"), Segment::Index(i), key("repository")],
+                        format!(
+                            "repository `{}` is not listed in `repositories`",
+                            repository
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// A problem for a serde error that validation did not catch.
+pub fn serde_problem(source: &str, error: &serde_json::Error) -> Problem {
+    let mut validator = Validator {
+        source,
+        positions: HashMap::new(),
+        strict: false,
+        problems: vec![],
+    };
+    validator.report_at(error.line(), error.column(), error.to_string());
+    validator.problems.remove(0)
+}
+
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Reports every problem with a tour file: syntax errors, missing fields, values of the wrong
+/// type, an unsupported protocol version, duplicate stop ids and stops in unlisted repositories.
+/// With `strict`, fields the format does not define are reported too.
+pub fn validate(source: &str, strict: bool) -> Vec<Problem> {
+    let mut validator = Validator {
+        source,
+        positions: HashMap::new(),
+        strict,
+        problems: vec![],
+    };
+    let root = match serde_json::from_str::<Value>(source) {
+        Ok(root) => root,
+        Err(e) => {
+            validator.report_at(e.line(), e.column(), e.to_string());
+            return validator.problems;
+        }
+    };
+    validator.positions = Locator::locate(source);
+
+    let members = match &root {
+        Value::Object(members) => members,
+        other => {
+            validator.report(
+                &[],
+                format!("expected an object, found {}", describe(other)),
+            );
+            return validator.problems;
+        }
+    };
+    match members.get("protocolVersion").and_then(Value::as_str) {
+        Some(version) if version != PROTOCOL_VERSION => {
+            validator.report(
+                &[key("protocolVersion")],
+                format!(
+                    "unsupported protocol version `{}`; expected `{}`",
+                    version, PROTOCOL_VERSION
+                ),
+            );
+            return validator.problems;
+        }
+        _ => {}
+    }
+    validator.check_object(&[], members, TOUR_FILE);
+    validator.check_references(members);
+    validator.problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Field, Kind, TOUR_FILE};
+    use crate::serialize::version1::TourFile;
+    use serde_json::{json, Value};
+
+    const TOUR: &str = r#"{
+  "protocolVersion": "1.0",
+  "id": "tour",
+  "title": "Tour",
+  "description": "",
+  "stops": [
+    { "id": "1", "title": "A", "body": "", "relPath": "a.rs",
+      "repository": "repo", "line": "3", "childStops": [] },
+    { "id": "1", "title": "B", "body": "", "relPath": "b.rs",
+      "repository": "other", "line": 4, "childStops": [], "colour": "red" }
+  ],
+  "repositories": [{ "repository": "repo", "commit": "abc" }]
+}"#;
+
+    #[test]
+    fn reports_every_problem_with_its_position() {
+        let problems = validate(TOUR, false)
+            .into_iter()
+            .map(|p| (p.line, p.column, p.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    8,
+                    29,
+                    "expected a non-negative integer, found a string".to_owned()
+                ),
+                (
+                    9,
+                    7,
+                    "duplicate stop id `1`, also used by stops[0]".to_owned()
+                ),
+                (
+                    10,
+                    7,
+                    "repository `other` is not listed in `repositories`".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_mode_reports_unknown_fields() {
+        let problems = validate(TOUR, true);
+        let unknown = problems
+            .iter()
+            .find(|p| p.message == "unknown field `colour`")
+            .unwrap();
+        assert_eq!((unknown.line, unknown.column), (10, 59));
+        assert!(unknown.snippet.ends_with(r#""colour": "red" }"#));
+    }
+
+    #[test]
+    fn locates_escaped_and_dotted_keys() {
+        let source = TOUR.replace(
+            r#""colour": "red" }"#,
+            r#""\u0063olour": "red", "a.b": 1 }"#,
+        );
+        let problems = validate(&source, true);
+        let at = |message: &str| {
+            let problem = problems.iter().find(|p| p.message == message).unwrap();
+            (problem.line, problem.column)
+        };
+        assert_eq!(at("unknown field `colour`"), (10, 59));
+        assert_eq!(at("unknown field `a.b`"), (10, 81));
+    }
+
+    /// A value with every field `kind` can hold.
+    fn sample(kind: &Kind) -> Value {
+        match kind {
+            Kind::String => json!("x"),
+            Kind::Count => json!(1),
+            Kind::Array(inner) => json!([sample(inner)]),
+            Kind::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|field| (field.name.to_owned(), sample(&field.kind)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Every field in `fields` and the objects nested in them, with the JSON pointer of the
+    /// object it is in within a sample.
+    fn all_fields(fields: &'static [Field], pointer: &str, out: &mut Vec<(String, &Field)>) {
+        for field in fields {
+            out.push((pointer.to_owned(), field));
+            let mut kind = &field.kind;
+            let mut inner = format!("{}/{}", pointer, field.name);
+            while let Kind::Array(element) = kind {
+                kind = element;
+                inner.push_str("/0");
+            }
+            if let Kind::Object(fields) = kind {
+                all_fields(fields, &inner, out);
+            }
+        }
+    }
+
+    #[test]
+    fn field_table_matches_version1() {
+        let round_trip = |value: &Value| {
+            serde_json::from_value::<TourFile>(value.clone())
+                .map(|tour| serde_json::to_value(tour).unwrap())
+        };
+        let full = sample(&Kind::Object(TOUR_FILE));
+        assert_eq!(round_trip(&full).unwrap(), full);
+
+        let mut fields = vec![];
+        all_fields(TOUR_FILE, "", &mut fields);
+        for (pointer, field) in fields {
+            let mut value = full.clone();
+            let object = value
+                .pointer_mut(&pointer)
+                .unwrap()
+                .as_object_mut()
+                .unwrap();
+            object.remove(field.name);
+            assert_eq!(
+                round_trip(&value).is_ok(),
+                !field.required,
+                "{}/{}",
+                pointer,
+                field.name
+            );
+        }
+    }
+
+    #[test]
+    fn reports_syntax_errors_and_missing_fields() {
+        assert_eq!(validate("{\n  \"id\": }", false)[0].line, 2);
+        let problems = validate(r#"{ "protocolVersion": "1.0", "id": "t" }"#, false);
+        assert_eq!(problems[0].message, "missing field `title`");
+        assert_eq!((problems[0].line, problems[0].column), (1, 1));
+    }
+}
@@ -14,6 +14,18 @@ pub struct Child {
     pub stop_num: usize,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Anchor {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub hash: String,
+    #[serde(default)]
+    pub before: Vec<String>,
+    #[serde(default)]
+    pub after: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Stop {
@@ -23,14 +35,29 @@ pub struct Stop {
     pub rel_path: String,
     pub repository: String,
     pub line: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<Anchor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
     pub child_stops: Vec<Child>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Fingerprint {
+    pub tree: String,
+    pub patch: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Repository {
     pub repository: String,
     pub commit: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -76,12 +103,20 @@ impl Into<types::Tour> for TourFile {
                     path: stop.rel_path.as_str().replace("\\", "/").into(),
                     repository: stop.repository,
                     line: stop.line,
+                    anchor: stop.anchor.map(|a| types::Anchor {
+                        text: a.text,
+                        hash: a.hash,
+                        before: a.before,
+                        after: a.after,
+                    }),
+                    blob: stop.blob,
                     children: stop
                         .child_stops
                         .into_iter()
                         .map(|c| types::StopReference {
                             tour_id: c.tour_id,
                             stop_id: "".to_owned(),
+                            stop_num: c.stop_num,
                         })
                         .collect::<Vec<_>>(),
                 })
@@ -91,12 +126,38 @@ impl Into<types::Tour> for TourFile {
                 .iter()
                 .map(|r| (r.repository.to_owned(), r.commit.to_owned()))
                 .collect::<HashMap<_, _>>(),
+            pinned_refs: self
+                .repositories
+                .iter()
+                .filter_map(|r| {
+                    r.pinned_ref
+                        .as_ref()
+                        .map(|p| (r.repository.to_owned(), p.to_owned()))
+                })
+                .collect::<HashMap<_, _>>(),
+            fingerprints: self
+                .repositories
+                .iter()
+                .filter_map(|r| {
+                    r.fingerprint.as_ref().map(|f| {
+                        (
+                            r.repository.to_owned(),
+                            types::CommitFingerprint {
+                                tree: f.tree.to_owned(),
+                                patch: f.patch.to_owned(),
+                            },
+                        )
+                    })
+                })
+                .collect::<HashMap<_, _>>(),
         }
     }
 }
 
 impl From<types::Tour> for TourFile {
     fn from(tour: types::Tour) -> Self {
+        let mut pinned_refs = tour.pinned_refs;
+        let mut fingerprints = tour.fingerprints;
         TourFile {
             protocol_version: tour.protocol_version,
             generator: Some(tour.generator),
@@ -113,12 +174,19 @@ impl From<types::Tour> for TourFile {
                     rel_path: stop.path.as_git_path(),
                     repository: stop.repository,
                     line: stop.line,
+                    anchor: stop.anchor.map(|a| Anchor {
+                        text: a.text,
+                        hash: a.hash,
+                        before: a.before,
+                        after: a.after,
+                    }),
+                    blob: stop.blob,
                     child_stops: stop
                         .children
                         .into_iter()
                         .map(|c| Child {
                             tour_id: c.tour_id,
-                            stop_num: 0,
+                            stop_num: c.stop_num,
                         })
                         .collect::<Vec<_>>(),
                 })
@@ -127,6 +195,11 @@ impl From<types::Tour> for TourFile {
                 .repositories
                 .into_iter()
                 .map(|(r, c)| Repository {
+                    pinned_ref: pinned_refs.remove(&r),
+                    fingerprint: fingerprints.remove(&r).map(|f| Fingerprint {
+                        tree: f.tree,
+                        patch: f.patch,
+                    }),
                     repository: r,
                     commit: c,
                 })
@@ -0,0 +1,138 @@
+/// Lines of surrounding context hashed on either side of an anchored line.
+pub const ANCHOR_CONTEXT: usize = 2;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The content a stop points at, kept so the stop can be found again when its line number can no
+/// longer be trusted. Lines are compared by the hash of their whitespace-normalized text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anchor {
+    pub text: Option<String>,
+    pub hash: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+fn normalize(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A stable FNV-1a hash of the normalized line, as 16 hex digits.
+pub fn hash_line(line: &str) -> String {
+    let hash = normalize(line).bytes().fold(FNV_OFFSET, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+    });
+    format!("{:016x}", hash)
+}
+
+impl Anchor {
+    /// Anchors the 1-indexed `line` of `lines`. Returns `None` if the line does not exist.
+    pub fn new(lines: &[&str], line: usize, keep_text: bool) -> Option<Self> {
+        let target = lines.get(line.checked_sub(1)?)?;
+        let start = (line - 1).saturating_sub(ANCHOR_CONTEXT);
+        let end = (line + ANCHOR_CONTEXT).min(lines.len());
+        Some(Anchor {
+            text: if keep_text {
+                Some((*target).to_owned())
+            } else {
+                None
+            },
+            hash: hash_line(target),
+            before: lines[start..line - 1]
+                .iter()
+                .map(|l| hash_line(l))
+                .collect(),
+            after: lines[line..end].iter().map(|l| hash_line(l)).collect(),
+        })
+    }
+
+    /// Whether the 1-indexed `line` of `lines` still holds the anchored content.
+    pub fn matches(&self, lines: &[&str], line: usize) -> bool {
+        line.checked_sub(1)
+            .and_then(|i| lines.get(i))
+            .map_or(false, |l| hash_line(l) == self.hash)
+    }
+
+    /// Fuzzy-searches `lines` for the anchor. Candidates are scored on the anchored line and on
+    /// each line of context that still matches; the anchored line weighs as much as all of its
+    /// context together. Only candidates scoring more than half the maximum are accepted, and ties
+    /// go to the candidate nearest `near`.
+    pub fn find(&self, lines: &[&str], near: usize) -> Option<usize> {
+        let hashes = lines.iter().map(|l| hash_line(l)).collect::<Vec<_>>();
+        let context = self.before.len() + self.after.len();
+        let target_weight = context.max(1);
+        let max_score = target_weight + context;
+
+        let score = |i: usize| {
+            let mut score = 0;
+            if hashes[i] == self.hash {
+                score += target_weight;
+            }
+            for (d, h) in self.before.iter().rev().enumerate() {
+                if i > d && hashes[i - d - 1] == *h {
+                    score += 1;
+                }
+            }
+            for (d, h) in self.after.iter().enumerate() {
+                if hashes.get(i + d + 1) == Some(h) {
+                    score += 1;
+                }
+            }
+            score
+        };
+
+        let distance = |line: usize| (line as isize - near as isize).abs();
+        (0..hashes.len())
+            .map(|i| (score(i), i + 1))
+            .filter(|(s, _)| s * 2 > max_score)
+            .max_by(|(s1, l1), (s2, l2)| s1.cmp(s2).then_with(|| distance(*l2).cmp(&distance(*l1))))
+            .map(|(_, l)| l)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_line, Anchor};
+
+    const FILE: &[&str] = &[
+        "fn a() {",
+        "    one();",
+        "}",
+        "",
+        "fn b() {",
+        "    two();",
+        "}",
+    ];
+
+    #[test]
+    fn hash_ignores_whitespace() {
+        assert_eq!(hash_line("  let x =  1;"), hash_line("let x = 1;\t"));
+        assert_ne!(hash_line("let x = 1;"), hash_line("let x = 2;"));
+    }
+
+    #[test]
+    fn anchor_matches_its_line() {
+        let anchor = Anchor::new(FILE, 6, true).unwrap();
+        assert_eq!(anchor.text, Some("    two();".to_owned()));
+        assert_eq!(anchor.before.len(), 2);
+        assert_eq!(anchor.after.len(), 1);
+        assert!(anchor.matches(FILE, 6));
+        assert!(!anchor.matches(FILE, 2));
+        assert!(Anchor::new(FILE, 0, false).is_none());
+        assert!(Anchor::new(FILE, 8, false).is_none());
+    }
+
+    #[test]
+    fn find_relocated_anchor() {
+        let anchor = Anchor::new(FILE, 6, false).unwrap();
+        let moved = ["// header", "", "fn b() {", "    two();", "}", "fn a() {}"];
+        assert_eq!(anchor.find(&moved, 6), Some(4));
+
+        let edited = ["fn b() {", "    two(3);", "}"];
+        assert_eq!(anchor.find(&edited, 6), None);
+
+        let renamed_context = ["", "fn b2() {", "    two();", "}"];
+        assert_eq!(anchor.find(&renamed_context, 1), Some(3));
+    }
+}
@@ -0,0 +1,203 @@
+use super::path::AbsolutePathBuf;
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// What an index rule matches a repository name against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleKey {
+    /// A pattern over the repository name, e.g. `org/*`.
+    Name(String),
+    /// A pattern over repository names that are remote URLs, e.g. `github.com/org/*`. URLs are
+    /// compared without their scheme, user, `.git` suffix or scp-style colon. Only the name the
+    /// tour uses is matched; the remotes configured in local checkouts are not consulted.
+    Remote(String),
+}
+
+/// Maps every repository matching `key` to `path`, with each `*` in `path` replaced by what the
+/// corresponding `*` in the key matched. A leading `~` in `path` is the home directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexRule {
+    pub key: RuleKey,
+    pub path: String,
+}
+
+impl IndexRule {
+    fn apply(&self, repository: &str) -> Option<PathBuf> {
+        let remote;
+        let captures = match &self.key {
+            RuleKey::Name(pattern) => match_pattern(pattern, repository)?,
+            RuleKey::Remote(pattern) => {
+                remote = normalize_remote(repository);
+                match_pattern(&normalize_remote(pattern), &remote)?
+            }
+        };
+        let mut path = String::new();
+        let mut captures = captures.into_iter();
+        for (i, part) in self.path.split('*').enumerate() {
+            if i > 0 {
+                path.push_str(captures.next().unwrap_or(""));
+            }
+            path.push_str(part);
+        }
+        Some(if path.starts_with("~/") {
+            dirs::home_dir()?.join(&path[2..])
+        } else {
+            PathBuf::from(path)
+        })
+    }
+
+    fn describe(&self) -> String {
+        match &self.key {
+            RuleKey::Name(pattern) => format!("{} -> {}", pattern, self.path),
+            RuleKey::Remote(pattern) => format!("remote {} -> {}", pattern, self.path),
+        }
+    }
+}
+
+/// Matches `text` against `pattern`, where each `*` matches one path segment, and returns what
+/// each `*` matched. A `*` never matches an empty segment, `.` or `..`, so a repository name
+/// cannot lead a rule outside the directory its path names.
+fn match_pattern<'a>(pattern: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    match pattern.find('*') {
+        None if pattern == text => Some(vec![]),
+        None => None,
+        Some(star) => {
+            let (prefix, rest) = (&pattern[..star], &pattern[star + 1..]);
+            if !text.starts_with(prefix) {
+                return None;
+            }
+            let text = &text[prefix.len()..];
+            let segment = text.find(|c| c == '/' || c == '\\').unwrap_or(text.len());
+            (1..=segment)
+                .rev()
+                .filter(|end| text.is_char_boundary(*end))
+                .filter(|end| &text[..*end] != "." && &text[..*end] != "..")
+                .find_map(|end| {
+                    let mut captures = match_pattern(rest, &text[end..])?;
+                    captures.insert(0, &text[..end]);
+                    Some(captures)
+                })
+        }
+    }
+}
+
+fn normalize_remote(url: &str) -> String {
+    let url = match url.find("://") {
+        Some(i) => url[i + 3..].to_owned(),
+        None => url.replacen(':', "/", 1),
+    };
+    let url = match url.find('@') {
+        Some(i) if url[..i].find('/').is_none() => &url[i + 1..],
+        _ => &url[..],
+    };
+    url.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_owned()
+}
+
+/// Where to find the repositories that tours refer to by name.
+#[derive(Clone, Default)]
+pub struct Index {
+    entries: HashMap<String, AbsolutePathBuf>,
+    rules: Vec<IndexRule>,
+}
+
+impl Index {
+    pub fn new(entries: HashMap<String, AbsolutePathBuf>, rules: Vec<IndexRule>) -> Self {
+        Index { entries, rules }
+    }
+
+    pub fn insert(&mut self, repository: String, path: AbsolutePathBuf) {
+        self.entries.insert(repository, path);
+    }
+
+    /// Finds a repository by exact name, then by the first rule that maps it to an existing
+    /// directory.
+    pub fn get(&self, repository: &str) -> Result<AbsolutePathBuf> {
+        if let Some(path) = self.entries.get(repository) {
+            return Ok(path.clone());
+        }
+        let mut tried = vec![];
+        for rule in &self.rules {
+            match rule.apply(repository) {
+                Some(path) if path.is_dir() => {
+                    if let Some(path) = AbsolutePathBuf::new(path) {
+                        return Ok(path);
+                    }
+                }
+                Some(path) => tried.push(format!(
+                    "{} (no directory at {})",
+                    rule.describe(),
+                    path.display()
+                )),
+                None => tried.push(format!("{} (no match)", rule.describe())),
+            }
+        }
+        Err(Error::NotInIndex(repository.to_owned(), tried))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_pattern, normalize_remote, Index, IndexRule, RuleKey};
+    use crate::error::Error;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn patterns_capture_each_wildcard() {
+        assert_eq!(match_pattern("org/*", "org/app"), Some(vec!["app"]));
+        assert_eq!(
+            match_pattern("*/*-svc", "org/auth-svc"),
+            Some(vec!["org", "auth"])
+        );
+        assert_eq!(match_pattern("org/*", "other/app"), None);
+        assert_eq!(match_pattern("org/*", "org/team/app"), None);
+        assert_eq!(match_pattern("org/*", "org/.."), None);
+        assert_eq!(match_pattern("org/*", "org/"), None);
+        assert_eq!(match_pattern("*-*", "a-b-c"), Some(vec!["a-b", "c"]));
+        assert_eq!(
+            normalize_remote("git@github.com:org/app.git"),
+            normalize_remote("https://github.com/org/app")
+        );
+    }
+
+    #[test]
+    fn rules_resolve_missing_repositories() {
+        let root = TempDir::new("src").unwrap().into_path();
+        fs::create_dir_all(root.join("org").join("app")).unwrap();
+        let rule = |key| IndexRule {
+            key,
+            path: format!("{}/org/*", root.display()),
+        };
+        let index = Index::new(
+            HashMap::new(),
+            vec![
+                rule(RuleKey::Name("org/*".to_owned())),
+                rule(RuleKey::Remote("github.com/org/*".to_owned())),
+            ],
+        );
+        let expected = root.join("org").join("app");
+        assert_eq!(
+            index.get("org/app").unwrap().as_absolute_path().as_path(),
+            expected
+        );
+        assert_eq!(
+            index
+                .get("git@github.com:org/app.git")
+                .unwrap()
+                .as_absolute_path()
+                .as_path(),
+            expected
+        );
+        match index.get("org/missing") {
+            Err(Error::NotInIndex(name, tried)) => {
+                assert_eq!(name, "org/missing");
+                assert_eq!(tried.len(), 2);
+            }
+            _ => panic!("expected org/missing not to resolve"),
+        }
+    }
+}
@@ -1,12 +1,18 @@
-use std::collections::HashMap;
+use crate::error::{Error, Result};
+use std::collections::{HashMap, HashSet};
 
+mod anchor;
+mod index;
 pub mod path;
 
-use path::{AbsolutePathBuf, RelativePathBuf};
+pub use anchor::Anchor;
+pub use index::{Index, IndexRule, RuleKey};
+use path::RelativePathBuf;
 
 pub struct StopReference {
     pub tour_id: String,
     pub stop_id: String,
+    pub stop_num: usize,
 }
 
 pub struct Stop {
@@ -16,9 +22,19 @@ pub struct Stop {
     pub path: RelativePathBuf,
     pub repository: String,
     pub line: usize,
+    pub anchor: Option<Anchor>,
+    /// The id of the stop file's blob at the tour's commit, used to recover rewritten commits.
+    pub blob: Option<String>,
     pub children: Vec<StopReference>,
 }
 
+/// What is known about a tour commit, so a rebased copy of it can be found if it disappears.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitFingerprint {
+    pub tree: String,
+    pub patch: String,
+}
+
 pub struct Tour {
     pub protocol_version: String,
     pub id: String,
@@ -26,7 +42,140 @@ pub struct Tour {
     pub description: String,
     pub stops: Vec<Stop>,
     pub repositories: HashMap<String, String>,
+    /// The symbolic reference each repository's commit was pinned from, if any.
+    pub pinned_refs: HashMap<String, String>,
+    pub fingerprints: HashMap<String, CommitFingerprint>,
     pub generator: usize,
 }
 
-pub type Index = HashMap<String, AbsolutePathBuf>;
+impl Tour {
+    /// Renames repositories throughout the tour, all at once, so two repositories can swap names.
+    /// Returns the names in `renames` that the tour has no repository by. Nothing is renamed if
+    /// two repositories would end up with the same name.
+    pub fn rename_repositories<'a>(
+        &mut self,
+        renames: &'a [(String, String)],
+    ) -> Result<Vec<&'a str>> {
+        let names = self
+            .repositories
+            .keys()
+            .chain(self.stops.iter().map(|stop| &stop.repository))
+            .cloned()
+            .collect::<HashSet<_>>();
+        let renames = renames
+            .iter()
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+            .collect::<Vec<_>>();
+        let rename = |name: &str| {
+            renames
+                .iter()
+                .rev()
+                .find(|(from, _)| *from == name)
+                .map_or(name, |(_, to)| to)
+                .to_owned()
+        };
+        let mut renamed = HashSet::new();
+        for name in &names {
+            let new = rename(name);
+            if !renamed.insert(new.clone()) {
+                return Err(Error::RenameConflict(new));
+            }
+        }
+
+        self.repositories = self
+            .repositories
+            .drain()
+            .map(|(name, commit)| (rename(&name), commit))
+            .collect();
+        self.pinned_refs = self
+            .pinned_refs
+            .drain()
+            .map(|(name, pinned)| (rename(&name), pinned))
+            .collect();
+        self.fingerprints = self
+            .fingerprints
+            .drain()
+            .map(|(name, fingerprint)| (rename(&name), fingerprint))
+            .collect();
+        for stop in &mut self.stops {
+            stop.repository = rename(&stop.repository);
+        }
+        Ok(renames
+            .into_iter()
+            .map(|(from, _)| from)
+            .filter(|from| !names.contains(*from))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Stop, Tour};
+    use crate::error::Error;
+    use std::collections::HashMap;
+
+    fn tour(repositories: &[&str]) -> Tour {
+        Tour {
+            protocol_version: "1.0".to_owned(),
+            id: "t".to_owned(),
+            title: "T".to_owned(),
+            description: String::new(),
+            stops: repositories
+                .iter()
+                .map(|repository| Stop {
+                    id: repository.to_string(),
+                    title: String::new(),
+                    description: String::new(),
+                    path: "a.rs".to_owned().into(),
+                    repository: repository.to_string(),
+                    line: 1,
+                    anchor: None,
+                    blob: None,
+                    children: vec![],
+                })
+                .collect(),
+            repositories: repositories
+                .iter()
+                .map(|repository| (repository.to_string(), format!("{}-commit", repository)))
+                .collect::<HashMap<_, _>>(),
+            pinned_refs: HashMap::new(),
+            fingerprints: HashMap::new(),
+            generator: 0,
+        }
+    }
+
+    fn renames(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn renames_apply_all_at_once() {
+        let mut tour = tour(&["a", "b", "c"]);
+        let swap = renames(&[("a", "b"), ("b", "a"), ("x", "y")]);
+        let unused = tour.rename_repositories(&swap).unwrap();
+        assert_eq!(unused, vec!["x"]);
+        assert_eq!(tour.repositories["a"], "b-commit");
+        assert_eq!(tour.repositories["b"], "a-commit");
+        assert_eq!(tour.repositories["c"], "c-commit");
+        let stops = tour
+            .stops
+            .iter()
+            .map(|stop| (stop.id.as_str(), stop.repository.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(stops, vec![("a", "b"), ("b", "a"), ("c", "c")]);
+    }
+
+    #[test]
+    fn renames_cannot_merge_repositories() {
+        let mut tour = tour(&["a", "b"]);
+        match tour.rename_repositories(&renames(&[("a", "b")])) {
+            Err(Error::RenameConflict(name)) => assert_eq!(name, "b"),
+            _ => panic!("expected a rename conflict"),
+        }
+        assert_eq!(tour.repositories["a"], "a-commit");
+        assert_eq!(tour.stops[0].repository, "a");
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct AbsolutePathBuf(PathBuf);
 
 impl AbsolutePathBuf {
@@ -17,7 +17,7 @@ impl AbsolutePathBuf {
     }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct AbsolutePath<'a>(&'a Path);
 
 impl<'a> AbsolutePath<'a> {
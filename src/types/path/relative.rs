@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 pub type Component = String;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RelativePathBuf(Vec<Component>);
 
 impl RelativePathBuf {
@@ -19,6 +19,10 @@ impl RelativePathBuf {
     pub fn as_git_path(&self) -> String {
         self.0.to_vec().join("/")
     }
+
+    pub fn components(&self) -> &[Component] {
+        &self.0
+    }
 }
 
 impl From<String> for RelativePathBuf {
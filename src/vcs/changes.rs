@@ -2,36 +2,265 @@ use crate::types::path::RelativePathBuf;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 
+/// How many lines on either side of a moved line are compared when scoring a cross-file move.
+const MOVE_CONTEXT: usize = 3;
+
 #[derive(Debug, PartialEq, Eq)]
-pub struct Changes(HashMap<RelativePathBuf, FileChanges>);
+pub struct Changes {
+    files: HashMap<RelativePathBuf, FileChanges>,
+    copies: HashMap<RelativePathBuf, Vec<RelativePathBuf>>,
+    hunks: HashMap<RelativePathBuf, Vec<Hunk>>,
+    removed_lines: HashMap<RelativePathBuf, HashMap<usize, String>>,
+    added_lines: HashMap<RelativePathBuf, HashMap<usize, String>>,
+}
+
+/// Where a line of the old version of a file ended up in the new version.
+#[derive(Debug, PartialEq)]
+pub enum LineFate {
+    Unchanged,
+    Moved {
+        line: usize,
+    },
+    /// The file was renamed. The line may also have moved within it.
+    Renamed {
+        path: RelativePathBuf,
+        line: usize,
+    },
+    /// The line is gone. `nearest` is the closest line around it that survived, in the file's new
+    /// location, and `moved` is set if the line's content turned up in another file.
+    Deleted {
+        nearest: Option<(RelativePathBuf, usize)>,
+        moved: Option<CrossFileMove>,
+    },
+}
+
+/// The bounds of a diff hunk, in 1-indexed lines. `start` is the line before the hunk when the
+/// hunk has no lines on that side.
+#[derive(Debug, PartialEq, Eq)]
+struct Hunk {
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+}
+
+impl Hunk {
+    fn old_next(&self) -> usize {
+        self.old_start + self.old_lines.max(1)
+    }
+
+    fn new_next(&self) -> usize {
+        self.new_start + self.new_lines.max(1)
+    }
+}
+
+/// A deleted line whose content reappeared in another file of the same diff.
+#[derive(Debug, PartialEq)]
+pub struct CrossFileMove {
+    pub path: RelativePathBuf,
+    pub line: usize,
+    /// Between 0 and 1. Lowered when the surrounding lines did not move along with the target,
+    /// or when several places match equally well.
+    pub confidence: f64,
+}
 
 impl Changes {
     pub fn new() -> Self {
-        Changes(HashMap::new())
+        Changes {
+            files: HashMap::new(),
+            copies: HashMap::new(),
+            hunks: HashMap::new(),
+            removed_lines: HashMap::new(),
+            added_lines: HashMap::new(),
+        }
     }
 
-    #[allow(dead_code)]
     pub fn for_file(&self, path: &RelativePathBuf) -> Option<&FileChanges> {
-        self.0.get(path)
+        self.files.get(path)
+    }
+
+    /// The files that were created as copies of `path`. The original file stays where it was,
+    /// so copies do not affect `for_file`.
+    pub fn copies_of(&self, path: &RelativePathBuf) -> &[RelativePathBuf] {
+        self.copies.get(path).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The text of the 1-indexed `line` of `path`, if the diff removed it.
+    pub fn removed_line(&self, path: &RelativePathBuf, line: usize) -> Option<&str> {
+        self.removed_lines.get(path)?.get(&line).map(String::as_str)
+    }
+
+    /// Works out what happened to the 1-indexed `line` of `path`. Lines outside the diff's hunks
+    /// are shifted by the lines added and removed above them.
+    pub fn map_line(&self, path: &RelativePathBuf, line: usize) -> LineFate {
+        let (new_path, changes, deletions) = match self.files.get(path) {
+            None => return LineFate::Unchanged,
+            Some(FileChanges::Deleted) => {
+                return LineFate::Deleted {
+                    nearest: None,
+                    moved: self.find_moved_line(path, line),
+                }
+            }
+            Some(FileChanges::Changed { changes, deletions }) => (path, changes, deletions),
+            Some(FileChanges::Renamed {
+                new_name,
+                changes,
+                deletions,
+            }) => (new_name, changes, deletions),
+        };
+        let fate = |new_line: usize| {
+            if new_path != path {
+                LineFate::Renamed {
+                    path: new_path.clone(),
+                    line: new_line,
+                }
+            } else if new_line == line {
+                LineFate::Unchanged
+            } else {
+                LineFate::Moved { line: new_line }
+            }
+        };
+
+        if deletions.contains(&line) {
+            let nearest = (1..=line + deletions.len())
+                .flat_map(|d| vec![Some(line + d), line.checked_sub(d)])
+                .filter_map(|l| l.filter(|l| *l > 0 && !deletions.contains(l)))
+                .filter_map(|l| match changes.get(&l) {
+                    Some(new_line) => Some(*new_line),
+                    None if l < line => Some(self.shift_line(path, l)),
+                    None => None,
+                })
+                .next();
+            return LineFate::Deleted {
+                nearest: nearest.map(|l| (new_path.clone(), l)),
+                moved: self.find_moved_line(path, line),
+            };
+        }
+        fate(match changes.get(&line) {
+            Some(new_line) => *new_line,
+            None => self.shift_line(path, line),
+        })
+    }
+
+    /// Whether the deleted `line` of `path` was rewritten, rather than dropped: the hunk that
+    /// removed it also added lines.
+    pub fn is_edited(&self, path: &RelativePathBuf, line: usize) -> bool {
+        let new_path = match self.files.get(path) {
+            Some(FileChanges::Renamed { new_name, .. }) => new_name,
+            _ => path,
+        };
+        let added = match self.added_lines.get(new_path) {
+            Some(added) => added,
+            None => return false,
+        };
+        self.hunks.get(path).map_or(false, |hunks| {
+            hunks
+                .iter()
+                .filter(|h| h.old_start <= line && line < h.old_start + h.old_lines)
+                .any(|h| {
+                    added
+                        .keys()
+                        .any(|l| h.new_start <= *l && *l < h.new_start + h.new_lines)
+                })
+        })
+    }
+
+    /// Maps a line outside every hunk by the offset of the nearest hunk above it.
+    fn shift_line(&self, path: &RelativePathBuf, line: usize) -> usize {
+        self.hunks
+            .get(path)
+            .and_then(|hunks| hunks.iter().rev().find(|h| h.old_next() <= line))
+            .map_or(line, |h| line + h.new_next() - h.old_next())
+    }
+
+    /// Looks for the content of a deleted line among the lines added to other files, as happens
+    /// when code is extracted into a new module.
+    pub fn find_moved_line(&self, path: &RelativePathBuf, line: usize) -> Option<CrossFileMove> {
+        let removed = self.removed_lines.get(path)?;
+        let target = removed.get(&line)?.trim();
+        if target.is_empty() {
+            return None;
+        }
+        let own_path = match self.files.get(path) {
+            Some(FileChanges::Renamed { new_name, .. }) => new_name,
+            _ => path,
+        };
+
+        let mut best: Vec<(f64, &RelativePathBuf, usize)> = vec![];
+        for (new_path, added) in self.added_lines.iter().filter(|(p, _)| *p != own_path) {
+            for (&new_line, _) in added.iter().filter(|(_, text)| text.trim() == target) {
+                let score = context_score(removed, line, added, new_line);
+                match best.first() {
+                    Some((s, _, _)) if *s > score => {}
+                    Some((s, _, _)) if (*s - score).abs() < std::f64::EPSILON => {
+                        best.push((score, new_path, new_line))
+                    }
+                    _ => best = vec![(score, new_path, new_line)],
+                }
+            }
+        }
+
+        let ties = best.len() as f64;
+        best.into_iter()
+            .min_by_key(|(_, p, l)| (p.as_git_path(), *l))
+            .map(|(score, p, l)| CrossFileMove {
+                path: p.clone(),
+                line: l,
+                confidence: score / ties,
+            })
     }
 
     pub fn process_file(&mut self, e: DiffFileEvent) {
+        if e.copied {
+            if let Some(to_path) = e.to {
+                self.copies.entry(e.from).or_default().push(to_path);
+            }
+            return;
+        }
         match e.to {
-            None => self.0.insert(e.from, FileChanges::Deleted),
+            None => self.files.insert(e.from, FileChanges::Deleted),
             Some(to_path) => {
                 if to_path == e.from {
-                    self.0.insert(e.from, FileChanges::new_changed())
+                    self.files.insert(e.from, FileChanges::new_changed())
                 } else {
-                    self.0.insert(e.from, FileChanges::new_renamed(to_path))
+                    self.files.insert(e.from, FileChanges::new_renamed(to_path))
                 }
             }
         };
     }
 
+    pub fn process_hunk(&mut self, e: DiffHunkEvent) {
+        let hunks = self.hunks.entry(e.key).or_default();
+        hunks.push(Hunk {
+            old_start: e.old_start as usize,
+            old_lines: e.old_lines as usize,
+            new_start: e.new_start as usize,
+            new_lines: e.new_lines as usize,
+        });
+        hunks.sort_by_key(|h| h.old_start);
+    }
+
     pub fn process_line(&mut self, e: DiffLineEvent) {
         let from = e.from.map(|v| v.try_into().unwrap());
         let to = e.to.map(|v| v.try_into().unwrap());
-        self.0.entry(e.key).and_modify(|m| {
+        match (from, to) {
+            (Some(f), None) => {
+                self.removed_lines
+                    .entry(e.key.clone())
+                    .or_default()
+                    .insert(f, e.content);
+            }
+            (None, Some(t)) => {
+                if let Some(new_key) = e.new_key {
+                    self.added_lines
+                        .entry(new_key)
+                        .or_default()
+                        .insert(t, e.content);
+                }
+            }
+            _ => {}
+        }
+        self.files.entry(e.key).and_modify(|m| {
             if let Some(f) = from {
                 match to {
                     None => m.line_deleted(f),
@@ -100,10 +329,49 @@ impl FileChanges {
 pub struct DiffFileEvent {
     pub from: RelativePathBuf,
     pub to: Option<RelativePathBuf>,
+    pub copied: bool,
+}
+
+pub struct DiffHunkEvent {
+    pub key: RelativePathBuf,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
 }
 
 pub struct DiffLineEvent {
     pub key: RelativePathBuf,
+    pub new_key: Option<RelativePathBuf>,
     pub from: Option<u32>,
     pub to: Option<u32>,
+    pub content: String,
+}
+
+/// Scores how well the deleted lines around `line` line up with the added lines around
+/// `new_line`. Only neighbours that were themselves deleted count, since those are the ones that
+/// would have moved with it.
+fn context_score(
+    removed: &HashMap<usize, String>,
+    line: usize,
+    added: &HashMap<usize, String>,
+    new_line: usize,
+) -> f64 {
+    let mut total = 1;
+    let mut matched = 1;
+    for d in 1..=MOVE_CONTEXT {
+        let pairs = [
+            (line.checked_sub(d), new_line.checked_sub(d)),
+            (Some(line + d), Some(new_line + d)),
+        ];
+        for (old, new) in pairs.iter() {
+            if let Some(old_text) = old.and_then(|o| removed.get(&o)) {
+                total += 1;
+                if new.and_then(|n| added.get(&n)).map(|t| t.trim()) == Some(old_text.trim()) {
+                    matched += 1;
+                }
+            }
+        }
+    }
+    f64::from(matched) / f64::from(total)
 }
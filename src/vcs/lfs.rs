@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SPEC_PREFIX: &str = "version https://git-lfs.github.com/spec/";
+const MAX_POINTER_SIZE: usize = 1024;
+
+/// The parsed contents of a Git LFS pointer file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LfsPointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+impl LfsPointer {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        if content.len() > MAX_POINTER_SIZE || !content.starts_with(SPEC_PREFIX.as_bytes()) {
+            return None;
+        }
+        let text = std::str::from_utf8(content).ok()?;
+        let mut oid = None;
+        let mut size = None;
+        for line in text.lines() {
+            if line.starts_with("oid sha256:") {
+                oid = Some(line["oid sha256:".len()..].to_owned());
+            } else if line.starts_with("size ") {
+                size = line["size ".len()..].parse().ok();
+            }
+        }
+        let oid = oid?;
+        if oid.len() != 64 || !oid.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(LfsPointer { oid, size: size? })
+    }
+
+    /// Where the object lives in the local LFS store of the repository at `git_dir`.
+    pub fn object_path(&self, git_dir: &Path) -> PathBuf {
+        git_dir
+            .join("lfs")
+            .join("objects")
+            .join(&self.oid[0..2])
+            .join(&self.oid[2..4])
+            .join(&self.oid)
+    }
+}
+
+/// The git directory shared by every worktree of the repository whose git directory is `git_dir`,
+/// which is where the LFS store lives. A linked worktree names it in its `commondir` file.
+pub fn common_dir(git_dir: &Path) -> PathBuf {
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => git_dir.join(common.trim_end()),
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{common_dir, LfsPointer};
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    const OID: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+
+    #[test]
+    fn parse_pointer() {
+        let pointer = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12345\n",
+            OID
+        );
+        assert_eq!(
+            LfsPointer::parse(pointer.as_bytes()),
+            Some(LfsPointer {
+                oid: OID.to_owned(),
+                size: 12345,
+            })
+        );
+        assert_eq!(
+            LfsPointer::parse(pointer.as_bytes())
+                .unwrap()
+                .object_path(Path::new(".git")),
+            Path::new(".git/lfs/objects/4d/7a").join(OID)
+        );
+    }
+
+    #[test]
+    fn linked_worktrees_use_the_common_dir() {
+        let git_dir = TempDir::new("git").unwrap().into_path();
+        let worktree_dir = git_dir.join("worktrees").join("topic");
+        fs::create_dir_all(&worktree_dir).unwrap();
+        fs::write(worktree_dir.join("commondir"), "../..\n").unwrap();
+        assert_eq!(common_dir(&git_dir), git_dir);
+        assert_eq!(common_dir(&worktree_dir), worktree_dir.join("../.."));
+    }
+
+    #[test]
+    fn reject_non_pointers() {
+        assert_eq!(LfsPointer::parse(b"fn main() {}"), None);
+        assert_eq!(
+            LfsPointer::parse(b"version https://git-lfs.github.com/spec/v1\nsize 12\n"),
+            None
+        );
+    }
+}
@@ -1,11 +1,57 @@
 use crate::error::{Error, Result};
-use crate::types::path::{AbsolutePath, RelativePathBuf};
-use git2::{DiffOptions, Oid, Repository};
+use crate::types::path::{AbsolutePath, AbsolutePathBuf, RelativePathBuf};
+use crate::types::CommitFingerprint;
+use cache::LruCache;
+use git2::{Delta, Diff, DiffFindOptions, DiffOptions, ErrorCode, ObjectType, Oid, Repository};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
 
+mod cache;
 mod changes;
+mod lfs;
+mod recover;
+#[cfg(test)]
+pub mod testing;
+mod text;
+
+pub use changes::{Changes, LineFate};
+use changes::{DiffFileEvent, DiffHunkEvent, DiffLineEvent};
+use lfs::LfsPointer;
+pub use recover::{Replacement, ReplacementReason};
+pub use text::{decode, expand_tabs};
+
+const BLOB_CACHE_CAPACITY: usize = 256;
+/// How many threads read blobs ahead of dump and package.
+const PREFETCH_THREADS: usize = 4;
+
+/// How `diff_with_version` pairs up files that were renamed or copied with edits. Thresholds are
+/// similarity percentages, as in `git diff -M` and `git diff -C`.
+#[derive(Clone, Copy, Debug)]
+pub struct SimilarityOptions {
+    pub renames: bool,
+    pub copies: bool,
+    pub threshold: u16,
+}
+
+impl Default for SimilarityOptions {
+    fn default() -> Self {
+        SimilarityOptions {
+            renames: true,
+            copies: false,
+            threshold: 50,
+        }
+    }
+}
 
-pub use changes::Changes;
-use changes::{DiffFileEvent, DiffLineEvent};
+/// The contents of a file, classified by whether they can be shown as text.
+pub enum FileContents {
+    Text(String),
+    Binary { size: usize },
+}
 
 pub trait VCS {
     fn lookup_file_bytes(
@@ -22,71 +68,296 @@ pub trait VCS {
         to: &str,
     ) -> Result<Changes>;
 
+    /// Diffs `from` against the working tree, including staged and unstaged changes.
     fn diff_with_worktree(&self, repo_path: AbsolutePath<'_>, from: &str) -> Result<Changes>;
 
+    /// Diffs `from` against the index, which holds only the staged changes.
+    fn diff_with_index(&self, repo_path: AbsolutePath<'_>, from: &str) -> Result<Changes>;
+
+    /// Resolves a branch, tag or (possibly abbreviated) commit id to a full commit id.
+    fn resolve_commit(&self, repo_path: AbsolutePath<'_>, reference: &str) -> Result<String>;
+
+    /// The best common ancestor of two commits, as a full commit id.
+    fn merge_base(&self, repo_path: AbsolutePath<'_>, one: &str, two: &str) -> Result<String>;
+
+    /// The first parent of `commit`, as a full commit id, or `None` for a root commit.
+    fn first_parent(&self, repo_path: AbsolutePath<'_>, commit: &str) -> Result<Option<String>>;
+
+    /// Lists the first-parent history leading from `from` to `to` as full commit ids, oldest
+    /// first and including both ends.
+    fn first_parent_history(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<String>>;
+
+    fn commit_exists(&self, repo_path: AbsolutePath<'_>, commit: &str) -> Result<bool>;
+
+    fn fingerprint(&self, repo_path: AbsolutePath<'_>, commit: &str) -> Result<CommitFingerprint>;
+
+    /// The id of the blob at `file_path` in `commit`.
+    fn blob_id(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        commit: &str,
+        file_path: &RelativePathBuf,
+    ) -> Result<String>;
+
+    /// Proposes a commit to stand in for one that no longer exists, using what was recorded
+    /// about it while it did.
+    fn find_replacement(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        fingerprint: Option<&CommitFingerprint>,
+        blobs: &[(RelativePathBuf, String)],
+    ) -> Result<Option<Replacement>>;
+
+    /// Creates or overwrites the ref `name` to point at `commit`.
+    fn create_ref(&self, repo_path: AbsolutePath<'_>, name: &str, commit: &str) -> Result<()>;
+
+    /// Deletes every ref under `prefix`, returning how many there were.
+    fn delete_refs(&self, repo_path: AbsolutePath<'_>, prefix: &str) -> Result<usize>;
+
+    /// Clones the repository at `source`, a local path or URL, into `into`.
+    fn clone_repository(&self, source: &str, into: &Path) -> Result<()>;
+
+    /// The directory git runs this repository's hooks from.
+    fn hooks_dir(&self, repo_path: AbsolutePath<'_>) -> Result<PathBuf>;
+
+    /// The files in the repository's index: what the next commit will hold, or, right after a
+    /// commit, what it holds.
+    fn tracked_files(&self, repo_path: AbsolutePath<'_>) -> Result<Vec<RelativePathBuf>>;
+
+    /// Reads `files`, as (repository, commit, path), ahead of the `lookup_file_bytes` calls that
+    /// will ask for them, in parallel where the backend can. This is only a hint: failures are
+    /// left for the later lookups to report.
+    fn prefetch_files(&self, _files: &[(AbsolutePathBuf, String, RelativePathBuf)]) {}
+
+    /// Looks up a file and decodes it as text with `\n` line endings, unless it is binary.
     fn lookup_file_contents(
         &self,
         repo_path: AbsolutePath<'_>,
         commit: &str,
         file_path: &RelativePathBuf,
-    ) -> Result<String> {
+    ) -> Result<FileContents> {
         let content = self.lookup_file_bytes(repo_path, commit, file_path)?;
-        Ok(std::str::from_utf8(&content)?.to_owned())
+        Ok(match text::decode(&content) {
+            Some(text) => FileContents::Text(text),
+            None => FileContents::Binary {
+                size: content.len(),
+            },
+        })
     }
 }
 
-pub struct Git;
+/// Whether `commit` is a full hex commit id, rather than a symbolic or abbreviated reference.
+pub fn is_pinned(commit: &str) -> bool {
+    commit.len() == 40 && commit.chars().all(|c| c.is_ascii_hexdigit())
+}
 
-impl VCS for Git {
-    fn lookup_file_bytes(
+/// Reads a blob, replacing a Git LFS pointer with the object it points to.
+fn read_blob(repo: &Repository, id: Oid) -> Result<Vec<u8>> {
+    let content = repo.find_blob(id)?.content().to_vec();
+    match LfsPointer::parse(&content) {
+        Some(pointer) => {
+            let object = pointer.object_path(&lfs::common_dir(repo.path()));
+            fs::read(&object).map_err(|_| Error::LfsObjectMissing(pointer.oid))
+        }
+        None => Ok(content),
+    }
+}
+
+/// Reads each (git dir, blob) pair, opening a repository handle per thread since handles can't
+/// be shared between threads. Blobs that can't be read are left out.
+fn read_blobs_in_parallel(blobs: Vec<(PathBuf, Oid)>) -> Vec<(Oid, Vec<u8>)> {
+    let chunk_size = (blobs.len() + PREFETCH_THREADS - 1) / PREFETCH_THREADS;
+    if chunk_size == 0 {
+        return vec![];
+    }
+    let workers = blobs
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let chunk = chunk.to_vec();
+            thread::spawn(move || {
+                let mut repos: HashMap<PathBuf, Repository> = HashMap::new();
+                let mut read = vec![];
+                for (git_dir, id) in chunk {
+                    if !repos.contains_key(&git_dir) {
+                        match Repository::open(&git_dir) {
+                            Ok(repo) => repos.insert(git_dir.clone(), repo),
+                            Err(_) => continue,
+                        };
+                    }
+                    if let Ok(content) = read_blob(&repos[&git_dir], id) {
+                        read.push((id, content));
+                    }
+                }
+                read
+            })
+        })
+        .collect::<Vec<_>>();
+    workers
+        .into_iter()
+        .flat_map(|worker| worker.join().unwrap_or_default())
+        .collect()
+}
+
+/// The git backend. Open repositories, resolved commit trees and recently read blobs are kept
+/// for the lifetime of the value, so a single command only pays for each lookup once.
+pub struct Git {
+    repos: RefCell<HashMap<PathBuf, Rc<Repository>>>,
+    trees: RefCell<HashMap<(PathBuf, String), Oid>>,
+    blobs: RefCell<LruCache<Oid, Vec<u8>>>,
+    similarity: SimilarityOptions,
+}
+
+impl Git {
+    pub fn new() -> Self {
+        Git {
+            repos: RefCell::new(HashMap::new()),
+            trees: RefCell::new(HashMap::new()),
+            blobs: RefCell::new(LruCache::new(BLOB_CACHE_CAPACITY)),
+            similarity: SimilarityOptions::default(),
+        }
+    }
+
+    pub fn with_similarity(mut self, similarity: SimilarityOptions) -> Self {
+        self.similarity = similarity;
+        self
+    }
+
+    fn repository(&self, repo_path: &Path) -> Result<Rc<Repository>> {
+        if let Some(repo) = self.repos.borrow().get(repo_path) {
+            return Ok(Rc::clone(repo));
+        }
+        let repo = Rc::new(Repository::open(repo_path)?);
+        self.repos
+            .borrow_mut()
+            .insert(repo_path.to_path_buf(), Rc::clone(&repo));
+        Ok(repo)
+    }
+
+    fn tree_id(&self, repo_path: &Path, repo: &Repository, commit: &str) -> Result<Oid> {
+        let key = (repo_path.to_path_buf(), commit.to_owned());
+        if let Some(id) = self.trees.borrow().get(&key) {
+            return Ok(*id);
+        }
+        let id = match repo.revparse_single(commit) {
+            Err(ref e) if e.code() == ErrorCode::NotFound && is_pinned(commit) => {
+                return Err(Error::CommitNotFound(commit.to_owned()))
+            }
+            obj => obj?.peel_to_tree()?.id(),
+        };
+        self.trees.borrow_mut().insert(key, id);
+        Ok(id)
+    }
+
+    /// Walks `file_path` from the tree of `commit`, following gitlink entries into submodules at
+    /// their recorded commits. Returns the repository holding the blob along with its id.
+    fn find_blob_id(
         &self,
-        repo_path: AbsolutePath<'_>,
+        repo_path: &Path,
         commit: &str,
         file_path: &RelativePathBuf,
-    ) -> Result<Vec<u8>> {
-        let repo = Repository::open(repo_path.as_path())?;
-        let rev = format!("{}:{}", commit, file_path.as_git_path());
-        let obj = repo.revparse_single(&rev)?;
-        let blob = obj.as_blob().ok_or(Error::RevParse(rev))?;
-        Ok(blob.content().to_vec())
+    ) -> Result<(Rc<Repository>, Oid)> {
+        let not_a_blob = || Error::RevParse(format!("{}:{}", commit, file_path.as_git_path()));
+        let components = file_path.components();
+        let mut repo_path = repo_path.to_path_buf();
+        let mut repo = self.repository(&repo_path)?;
+        let mut current = self.tree_id(&repo_path, &repo, commit)?;
+        let mut submodule_start = 0;
+        for (i, name) in components.iter().enumerate() {
+            let (kind, id) = {
+                let tree = repo.find_tree(current)?;
+                let entry = tree.get_name(name).ok_or_else(|| {
+                    git2::Error::from_str(&format!(
+                        "the path '{}' does not exist in the given tree",
+                        components[..=i].join("/")
+                    ))
+                })?;
+                (entry.kind(), entry.id())
+            };
+            match kind {
+                Some(ObjectType::Tree) => current = id,
+                Some(ObjectType::Blob) if i + 1 == components.len() => return Ok((repo, id)),
+                Some(ObjectType::Commit) => {
+                    components[submodule_start..=i]
+                        .iter()
+                        .for_each(|c| repo_path.push(c));
+                    submodule_start = i + 1;
+                    repo = self
+                        .repository(&repo_path)
+                        .map_err(|_| Error::SubmoduleNotCheckedOut(components[..=i].join("/")))?;
+                    current = self.tree_id(&repo_path, &repo, &id.to_string())?;
+                }
+                _ => return Err(not_a_blob()),
+            }
+        }
+        Err(not_a_blob())
     }
 
-    fn diff_with_version(
-        &self,
-        repo_path: AbsolutePath<'_>,
-        from: &str,
-        to: &str,
-    ) -> Result<Changes> {
-        let repo = Repository::open(repo_path.as_path())?;
-        let from_tree = repo.find_commit(Oid::from_str(from)?)?.tree()?;
-        let to_tree = repo.find_commit(Oid::from_str(to)?)?.tree()?;
-
-        let diff = repo.diff_tree_to_tree(
-            Some(&from_tree),
-            Some(&to_tree),
-            Some(&mut DiffOptions::new().minimal(true).ignore_whitespace_eol(true)),
-        )?;
+    /// Runs rename and copy detection on `diff`, then replays it into a `Changes`.
+    fn collect_changes(&self, diff: &mut Diff<'_>) -> Result<Changes> {
+        if self.similarity.renames || self.similarity.copies {
+            diff.find_similar(Some(
+                DiffFindOptions::new()
+                    .renames(self.similarity.renames)
+                    .copies(self.similarity.copies)
+                    .rename_threshold(self.similarity.threshold)
+                    .copy_threshold(self.similarity.threshold),
+            ))?;
+        }
 
         let mut file_events = vec![];
+        let mut hunk_events = vec![];
         let mut line_events = vec![];
         diff.foreach(
             &mut |delta, _| {
+                if delta.status() == Delta::Added {
+                    return true;
+                }
                 if let Some(r) = delta.old_file().path().map(RelativePathBuf::from) {
                     file_events.push(DiffFileEvent {
                         from: r,
-                        to: delta.new_file().path().map(RelativePathBuf::from),
+                        to: match delta.status() {
+                            Delta::Deleted => None,
+                            _ => delta.new_file().path().map(RelativePathBuf::from),
+                        },
+                        copied: delta.status() == Delta::Copied,
                     });
                 }
                 true
             },
             None,
-            None,
+            Some(&mut |delta, hunk| {
+                if delta.status() == Delta::Copied {
+                    return true;
+                }
+                if let Some(r) = delta.old_file().path().map(RelativePathBuf::from) {
+                    hunk_events.push(DiffHunkEvent {
+                        key: r,
+                        old_start: hunk.old_start(),
+                        old_lines: hunk.old_lines(),
+                        new_start: hunk.new_start(),
+                        new_lines: hunk.new_lines(),
+                    });
+                }
+                true
+            }),
             Some(&mut |delta, _, line| {
+                if delta.status() == Delta::Copied {
+                    return true;
+                }
                 if let Some(r) = delta.old_file().path().map(RelativePathBuf::from) {
                     line_events.push(DiffLineEvent {
                         key: r,
+                        new_key: delta.new_file().path().map(RelativePathBuf::from),
                         from: line.old_lineno(),
                         to: line.new_lineno(),
+                        content: String::from_utf8_lossy(line.content())
+                            .trim_end_matches(&['\r', '\n'][..])
+                            .to_owned(),
                     });
                 }
                 true
@@ -96,59 +367,235 @@ impl VCS for Git {
         file_events
             .into_iter()
             .for_each(|e| changes.process_file(e));
+        hunk_events
+            .into_iter()
+            .for_each(|e| changes.process_hunk(e));
         line_events
             .into_iter()
             .for_each(|e| changes.process_line(e));
         Ok(changes)
     }
+}
 
-    fn diff_with_worktree(&self, _repo_path: AbsolutePath<'_>, _from: &str) -> Result<Changes> {
-        unimplemented!()
+impl Default for Git {
+    fn default() -> Self {
+        Git::new()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::changes::FileChanges;
-    use super::{Git, VCS};
-    use crate::types::path::{AbsolutePathBuf, RelativePathBuf};
-    use git2::{Commit, ObjectType, Oid, Repository, Signature};
-    use std::fs;
-    use std::path::Path;
-    use std::str;
-    use tempdir::TempDir;
+impl VCS for Git {
+    fn lookup_file_bytes(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        commit: &str,
+        file_path: &RelativePathBuf,
+    ) -> Result<Vec<u8>> {
+        let (repo, blob_id) = self.find_blob_id(repo_path.as_path(), commit, file_path)?;
+        if let Some(content) = self.blobs.borrow_mut().get(&blob_id) {
+            return Ok(content.clone());
+        }
+        let content = read_blob(&repo, blob_id)?;
+        self.blobs.borrow_mut().insert(blob_id, content.clone());
+        Ok(content)
+    }
 
-    fn find_last_commit(repo: &Repository) -> Result<Commit, git2::Error> {
-        let obj = repo.head()?.resolve()?.peel(ObjectType::Commit)?;
-        obj.into_commit()
-            .map_err(|_| git2::Error::from_str("Couldn't find commit"))
+    /// Finds the blobs serially, since that mostly hits the tree cache, then reads and inflates
+    /// them on several threads into the blob cache.
+    fn prefetch_files(&self, files: &[(AbsolutePathBuf, String, RelativePathBuf)]) {
+        let mut wanted = vec![];
+        for (repo_path, commit, file_path) in files {
+            let path = repo_path.as_absolute_path();
+            if let Ok((repo, id)) = self.find_blob_id(path.as_path(), commit, file_path) {
+                let cached = self.blobs.borrow_mut().get(&id).is_some();
+                if !cached && wanted.iter().all(|(_, other)| *other != id) {
+                    wanted.push((repo.path().to_path_buf(), id));
+                }
+            }
+        }
+        wanted.truncate(BLOB_CACHE_CAPACITY);
+        let mut blobs = self.blobs.borrow_mut();
+        for (id, content) in read_blobs_in_parallel(wanted) {
+            blobs.insert(id, content);
+        }
     }
 
-    fn add_files<P: AsRef<Path>>(repo: &Repository, files: Vec<P>) -> Result<Oid, git2::Error> {
-        let mut index = repo.index()?;
-        for file in files {
-            index.add_path(file.as_ref())?;
+    fn diff_with_version(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        from: &str,
+        to: &str,
+    ) -> Result<Changes> {
+        let repo = self.repository(repo_path.as_path())?;
+        let from_tree = repo.find_commit(Oid::from_str(from)?)?.tree()?;
+        let to_tree = repo.find_commit(Oid::from_str(to)?)?.tree()?;
+
+        let mut diff = repo.diff_tree_to_tree(
+            Some(&from_tree),
+            Some(&to_tree),
+            Some(&mut DiffOptions::new().minimal(true).ignore_whitespace_eol(true)),
+        )?;
+        self.collect_changes(&mut diff)
+    }
+
+    fn diff_with_worktree(&self, repo_path: AbsolutePath<'_>, from: &str) -> Result<Changes> {
+        let repo = self.repository(repo_path.as_path())?;
+        let from_tree = repo.find_commit(Oid::from_str(from)?)?.tree()?;
+
+        let mut diff = repo.diff_tree_to_workdir_with_index(
+            Some(&from_tree),
+            Some(&mut DiffOptions::new().minimal(true).ignore_whitespace_eol(true)),
+        )?;
+        self.collect_changes(&mut diff)
+    }
+
+    fn diff_with_index(&self, repo_path: AbsolutePath<'_>, from: &str) -> Result<Changes> {
+        let repo = self.repository(repo_path.as_path())?;
+        let from_tree = repo.find_commit(Oid::from_str(from)?)?.tree()?;
+
+        let mut diff = repo.diff_tree_to_index(
+            Some(&from_tree),
+            None,
+            Some(&mut DiffOptions::new().minimal(true).ignore_whitespace_eol(true)),
+        )?;
+        self.collect_changes(&mut diff)
+    }
+
+    fn resolve_commit(&self, repo_path: AbsolutePath<'_>, reference: &str) -> Result<String> {
+        let repo = self.repository(repo_path.as_path())?;
+        let commit = repo.revparse_single(reference)?.peel_to_commit()?;
+        Ok(commit.id().to_string())
+    }
+
+    fn merge_base(&self, repo_path: AbsolutePath<'_>, one: &str, two: &str) -> Result<String> {
+        let repo = self.repository(repo_path.as_path())?;
+        let one = repo.revparse_single(one)?.peel_to_commit()?.id();
+        let two = repo.revparse_single(two)?.peel_to_commit()?.id();
+        Ok(repo.merge_base(one, two)?.to_string())
+    }
+
+    fn first_parent(&self, repo_path: AbsolutePath<'_>, commit: &str) -> Result<Option<String>> {
+        let repo = self.repository(repo_path.as_path())?;
+        let commit = repo.revparse_single(commit)?.peel_to_commit()?;
+        Ok(commit.parent_ids().next().map(|id| id.to_string()))
+    }
+
+    fn first_parent_history(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<String>> {
+        let repo = self.repository(repo_path.as_path())?;
+        let start = repo.revparse_single(from)?.peel_to_commit()?.id();
+        let mut current = repo.revparse_single(to)?.peel_to_commit()?;
+        let mut history = vec![current.id().to_string()];
+        while current.id() != start {
+            if current.parent_count() == 0 {
+                return Err(Error::NotFirstParentAncestor(
+                    from.to_owned(),
+                    to.to_owned(),
+                ));
+            }
+            current = current.parent(0)?;
+            history.push(current.id().to_string());
         }
-        index.write_tree()
+        history.reverse();
+        Ok(history)
     }
 
-    fn commit(repo: &Repository, oid: Oid, message: &str) -> Result<Oid, git2::Error> {
-        let signature = Signature::now("Test User", "test@user.net")?;
-        let tree = repo.find_tree(oid)?;
-        let parent = match find_last_commit(&repo) {
-            Ok(p) => vec![p],
-            Err(_) => vec![],
-        };
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            message,
-            &tree,
-            &parent.iter().map(|x| &*x).collect::<Vec<_>>(),
-        )
+    fn commit_exists(&self, repo_path: AbsolutePath<'_>, commit: &str) -> Result<bool> {
+        let repo = self.repository(repo_path.as_path())?;
+        let exists = repo
+            .revparse_single(commit)
+            .and_then(|obj| obj.peel_to_commit())
+            .is_ok();
+        Ok(exists)
+    }
+
+    fn fingerprint(&self, repo_path: AbsolutePath<'_>, commit: &str) -> Result<CommitFingerprint> {
+        let repo = self.repository(repo_path.as_path())?;
+        let commit = repo.revparse_single(commit)?.peel_to_commit()?;
+        recover::fingerprint(&repo, &commit)
+    }
+
+    fn blob_id(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        commit: &str,
+        file_path: &RelativePathBuf,
+    ) -> Result<String> {
+        let (_, id) = self.find_blob_id(repo_path.as_path(), commit, file_path)?;
+        Ok(id.to_string())
+    }
+
+    fn find_replacement(
+        &self,
+        repo_path: AbsolutePath<'_>,
+        fingerprint: Option<&CommitFingerprint>,
+        blobs: &[(RelativePathBuf, String)],
+    ) -> Result<Option<Replacement>> {
+        let repo = self.repository(repo_path.as_path())?;
+        recover::find_replacement(&repo, fingerprint, blobs)
+    }
+
+    fn create_ref(&self, repo_path: AbsolutePath<'_>, name: &str, commit: &str) -> Result<()> {
+        let repo = self.repository(repo_path.as_path())?;
+        let id = repo.revparse_single(commit)?.peel_to_commit()?.id();
+        repo.reference(name, id, true, "tourist: pin tour commit")?;
+        Ok(())
+    }
+
+    fn clone_repository(&self, source: &str, into: &Path) -> Result<()> {
+        Repository::clone(source, into)?;
+        Ok(())
+    }
+
+    fn hooks_dir(&self, repo_path: AbsolutePath<'_>) -> Result<PathBuf> {
+        let repo = self.repository(repo_path.as_path())?;
+        let configured = repo.config()?.get_path("core.hooksPath").ok();
+        Ok(match configured {
+            Some(path) if path.is_absolute() => path,
+            Some(path) => repo_path.as_path().join(path),
+            None => repo.path().join("hooks"),
+        })
     }
 
+    fn tracked_files(&self, repo_path: AbsolutePath<'_>) -> Result<Vec<RelativePathBuf>> {
+        let repo = self.repository(repo_path.as_path())?;
+        let mut index = repo.index()?;
+        index.read(false)?;
+        Ok(index
+            .iter()
+            .map(|entry| RelativePathBuf::from(String::from_utf8_lossy(&entry.path).into_owned()))
+            .collect())
+    }
+
+    fn delete_refs(&self, repo_path: AbsolutePath<'_>, prefix: &str) -> Result<usize> {
+        let repo = self.repository(repo_path.as_path())?;
+        let mut count = 0;
+        for reference in repo.references_glob(&format!("{}/*", prefix))? {
+            reference?.delete()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::changes::{FileChanges, LineFate};
+    use super::testing::{add_files, commit};
+    use super::text::is_binary;
+    use super::{is_pinned, Git, Replacement, ReplacementReason, SimilarityOptions, VCS};
+    use crate::error::Error;
+    use crate::relocate::{Location, Mode, Relocation, Relocator};
+    use crate::types::path::{AbsolutePathBuf, RelativePathBuf};
+    use git2::{Oid, Repository, ResetType};
+    use std::fs;
+    use std::path::Path;
+    use std::str;
+    use tempdir::TempDir;
+
     #[test]
     fn figure_out_diff() {
         let repo_dir = TempDir::new("my_repo").unwrap().into_path();
@@ -169,7 +616,7 @@ mod tests {
         let oid = add_files(&repo, vec!["test.txt", "test2.txt"]).unwrap();
         let to_id = commit(&repo, oid, "commit 2").unwrap();
 
-        let changes = Git
+        let changes = Git::new()
             .diff_with_version(
                 AbsolutePathBuf::new(repo_dir.clone())
                     .unwrap()
@@ -187,4 +634,590 @@ mod tests {
             changes.for_file(&RelativePathBuf::from(Path::new("test.txt")))
         )
     }
+
+    #[test]
+    fn lookup_reuses_cached_handles() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        fs::write(repo_dir.join("test.txt"), "first").unwrap();
+        let oid = add_files(&repo, vec!["test.txt"]).unwrap();
+        let first = commit(&repo, oid, "commit 1").unwrap();
+
+        fs::write(repo_dir.join("test.txt"), "second").unwrap();
+        let oid = add_files(&repo, vec!["test.txt"]).unwrap();
+        let second = commit(&repo, oid, "commit 2").unwrap();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let path = RelativePathBuf::from("test.txt".to_owned());
+        for _ in 0..2 {
+            let content = git
+                .lookup_file_bytes(repo_path.as_absolute_path(), &first.to_string(), &path)
+                .unwrap();
+            assert_eq!(str::from_utf8(&content).unwrap(), "first");
+            let content = git
+                .lookup_file_bytes(repo_path.as_absolute_path(), &second.to_string(), &path)
+                .unwrap();
+            assert_eq!(str::from_utf8(&content).unwrap(), "second");
+        }
+        assert_eq!(git.repos.borrow().len(), 1);
+        assert_eq!(git.trees.borrow().len(), 2);
+    }
+
+    #[test]
+    fn prefetch_fills_blob_cache() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+        let names = (0..20).map(|i| format!("{}.txt", i)).collect::<Vec<_>>();
+        for (i, name) in names.iter().enumerate() {
+            fs::write(repo_dir.join(name), format!("file {}", i)).unwrap();
+        }
+        let oid = add_files(&repo, names.clone()).unwrap();
+        let head = commit(&repo, oid, "commit 1").unwrap().to_string();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let mut files = names
+            .iter()
+            .map(|name| {
+                (
+                    repo_path.clone(),
+                    head.clone(),
+                    RelativePathBuf::from(name.clone()),
+                )
+            })
+            .collect::<Vec<_>>();
+        files.push((
+            repo_path.clone(),
+            head.clone(),
+            RelativePathBuf::from("missing.txt".to_owned()),
+        ));
+        git.prefetch_files(&files);
+        for (i, name) in names.iter().enumerate() {
+            let (_, id) = git
+                .find_blob_id(&repo_dir, &head, &RelativePathBuf::from(name.clone()))
+                .unwrap();
+            let cached = git.blobs.borrow_mut().get(&id).cloned();
+            assert_eq!(cached, Some(format!("file {}", i).into_bytes()));
+        }
+    }
+
+    #[test]
+    fn merge_base_finds_the_fork_point() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        fs::write(repo_dir.join("test.txt"), "first").unwrap();
+        let oid = add_files(&repo, vec!["test.txt"]).unwrap();
+        let fork = commit(&repo, oid, "commit 1").unwrap();
+        fs::write(repo_dir.join("test.txt"), "main").unwrap();
+        let oid = add_files(&repo, vec!["test.txt"]).unwrap();
+        let main = commit(&repo, oid, "commit 2").unwrap();
+
+        repo.reset(
+            &repo.find_object(fork, None).unwrap(),
+            ResetType::Hard,
+            None,
+        )
+        .unwrap();
+        fs::write(repo_dir.join("test.txt"), "topic").unwrap();
+        let oid = add_files(&repo, vec!["test.txt"]).unwrap();
+        let topic = commit(&repo, oid, "commit 3").unwrap();
+
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let base = Git::new()
+            .merge_base(
+                repo_path.as_absolute_path(),
+                &main.to_string(),
+                &topic.to_string(),
+            )
+            .unwrap();
+        assert_eq!(base, fork.to_string());
+
+        let git = Git::new();
+        let parent = |commit: Oid| {
+            git.first_parent(repo_path.as_absolute_path(), &commit.to_string())
+                .unwrap()
+        };
+        assert_eq!(parent(topic), Some(fork.to_string()));
+        assert_eq!(parent(fork), None);
+    }
+
+    #[test]
+    fn resolve_commit_pins_references() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        fs::write(repo_dir.join("test.txt"), "content").unwrap();
+        let oid = add_files(&repo, vec!["test.txt"]).unwrap();
+        let id = commit(&repo, oid, "commit 1").unwrap().to_string();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        for reference in &["HEAD", &id[..8], &id] {
+            let resolved = git
+                .resolve_commit(repo_path.as_absolute_path(), reference)
+                .unwrap();
+            assert_eq!(resolved, id);
+            assert!(is_pinned(&resolved));
+        }
+        assert!(!is_pinned("HEAD"));
+        assert!(!is_pinned(&id[..8]));
+    }
+
+    #[test]
+    fn lookup_follows_submodules() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+        let sub_dir = repo_dir.join("vendor").join("lib");
+        fs::create_dir_all(&sub_dir).unwrap();
+        let sub = Repository::init(&sub_dir).unwrap();
+
+        fs::write(sub_dir.join("lib.txt"), "vendored").unwrap();
+        let oid = add_files(&sub, vec!["lib.txt"]).unwrap();
+        commit(&sub, oid, "sub commit").unwrap();
+
+        let oid = add_files(&repo, vec!["vendor/lib"]).unwrap();
+        let id = commit(&repo, oid, "commit 1").unwrap();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let content = git
+            .lookup_file_bytes(
+                repo_path.as_absolute_path(),
+                &id.to_string(),
+                &RelativePathBuf::from("vendor/lib/lib.txt".to_owned()),
+            )
+            .unwrap();
+        assert_eq!(str::from_utf8(&content).unwrap(), "vendored");
+    }
+
+    #[test]
+    fn lookup_resolves_lfs_pointers() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        let oid = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+        fs::write(
+            repo_dir.join("big.bin"),
+            format!(
+                "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 4\n",
+                oid
+            ),
+        )
+        .unwrap();
+        let tree = add_files(&repo, vec!["big.bin"]).unwrap();
+        let id = commit(&repo, tree, "commit 1").unwrap().to_string();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let path = RelativePathBuf::from("big.bin".to_owned());
+        assert!(git
+            .lookup_file_bytes(repo_path.as_absolute_path(), &id, &path)
+            .is_err());
+
+        let store = repo.path().join("lfs/objects/4d/7a");
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join(oid), b"\0\x01\x02\x03").unwrap();
+        let content = Git::new()
+            .lookup_file_bytes(repo_path.as_absolute_path(), &id, &path)
+            .unwrap();
+        assert_eq!(content, b"\0\x01\x02\x03");
+        assert!(is_binary(&content));
+        assert!(!is_binary(b"plain text"));
+    }
+
+    #[test]
+    fn diff_follows_renames_and_copies() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        let body = (1..=20)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        fs::write(repo_dir.join("old.txt"), &body).unwrap();
+        fs::write(repo_dir.join("source.txt"), "a\nb\nc\nd\ne\n").unwrap();
+        let oid = add_files(&repo, vec!["old.txt", "source.txt"]).unwrap();
+        let from_id = commit(&repo, oid, "commit 1").unwrap();
+
+        fs::remove_file(repo_dir.join("old.txt")).unwrap();
+        fs::write(repo_dir.join("new.txt"), format!("header\n{}", body)).unwrap();
+        fs::write(repo_dir.join("source.txt"), "a\nb\nc\nd\ne\nf\n").unwrap();
+        fs::write(repo_dir.join("copy.txt"), "a\nb\nc\nd\ne\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("old.txt")).unwrap();
+        index.write().unwrap();
+        let oid = add_files(&repo, vec!["new.txt", "source.txt", "copy.txt"]).unwrap();
+        let to_id = commit(&repo, oid, "commit 2").unwrap();
+
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let changes = Git::new()
+            .with_similarity(SimilarityOptions {
+                renames: true,
+                copies: true,
+                threshold: 50,
+            })
+            .diff_with_version(
+                repo_path.as_absolute_path(),
+                &from_id.to_string(),
+                &to_id.to_string(),
+            )
+            .unwrap();
+
+        match changes.for_file(&RelativePathBuf::from("old.txt".to_owned())) {
+            Some(FileChanges::Renamed {
+                new_name, changes, ..
+            }) => {
+                assert_eq!(new_name, &RelativePathBuf::from("new.txt".to_owned()));
+                assert_eq!(changes.get(&1), Some(&2));
+            }
+            other => panic!("expected a rename, got {:?}", other),
+        }
+        assert_eq!(
+            changes.copies_of(&RelativePathBuf::from("source.txt".to_owned())),
+            &[RelativePathBuf::from("copy.txt".to_owned())]
+        );
+    }
+
+    #[test]
+    fn find_code_moved_between_files() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        fs::write(
+            repo_dir.join("big.rs"),
+            "fn keep() {}\n\nfn helper() {\n    do_thing();\n}\n\nfn other() {}\n",
+        )
+        .unwrap();
+        let oid = add_files(&repo, vec!["big.rs"]).unwrap();
+        let from_id = commit(&repo, oid, "commit 1").unwrap();
+
+        fs::write(repo_dir.join("big.rs"), "fn keep() {}\n\nfn other() {}\n").unwrap();
+        fs::write(
+            repo_dir.join("helper.rs"),
+            "use big;\n\nfn helper() {\n    do_thing();\n}\n",
+        )
+        .unwrap();
+        let oid = add_files(&repo, vec!["big.rs", "helper.rs"]).unwrap();
+        let to_id = commit(&repo, oid, "commit 2").unwrap();
+
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let changes = Git::new()
+            .diff_with_version(
+                repo_path.as_absolute_path(),
+                &from_id.to_string(),
+                &to_id.to_string(),
+            )
+            .unwrap();
+
+        let path = RelativePathBuf::from("big.rs".to_owned());
+        let moved = changes.find_moved_line(&path, 4).unwrap();
+        assert_eq!(moved.path, RelativePathBuf::from("helper.rs".to_owned()));
+        assert_eq!(moved.line, 4);
+        assert!(moved.confidence > 0.5);
+        assert!(changes.find_moved_line(&path, 1).is_none());
+    }
+
+    #[test]
+    fn map_line_accounts_for_hunk_offsets() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        let lines = (1..=30).map(|i| format!("line {}", i)).collect::<Vec<_>>();
+        fs::write(repo_dir.join("file.txt"), lines.join("\n")).unwrap();
+        fs::write(repo_dir.join("gone.txt"), "bye").unwrap();
+        let oid = add_files(&repo, vec!["file.txt", "gone.txt"]).unwrap();
+        let from_id = commit(&repo, oid, "commit 1").unwrap();
+
+        let mut edited = vec!["new a".to_owned(), "new b".to_owned()];
+        edited.extend(lines.iter().take(14).cloned());
+        edited.extend(lines.iter().skip(15).cloned());
+        fs::write(repo_dir.join("file.txt"), edited.join("\n")).unwrap();
+        fs::remove_file(repo_dir.join("gone.txt")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("gone.txt")).unwrap();
+        index.write().unwrap();
+        let oid = add_files(&repo, vec!["file.txt"]).unwrap();
+        let to_id = commit(&repo, oid, "commit 2").unwrap();
+
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let changes = Git::new()
+            .diff_with_version(
+                repo_path.as_absolute_path(),
+                &from_id.to_string(),
+                &to_id.to_string(),
+            )
+            .unwrap();
+
+        let file = RelativePathBuf::from("file.txt".to_owned());
+        assert_eq!(changes.map_line(&file, 1), LineFate::Moved { line: 3 });
+        assert_eq!(changes.map_line(&file, 10), LineFate::Moved { line: 12 });
+        assert_eq!(changes.map_line(&file, 30), LineFate::Moved { line: 31 });
+        assert_eq!(
+            changes.map_line(&file, 15),
+            LineFate::Deleted {
+                nearest: Some((file.clone(), 17)),
+                moved: None,
+            }
+        );
+        assert_eq!(
+            changes.map_line(&RelativePathBuf::from("other.txt".to_owned()), 4),
+            LineFate::Unchanged
+        );
+        assert_eq!(
+            changes.map_line(&RelativePathBuf::from("gone.txt".to_owned()), 1),
+            LineFate::Deleted {
+                nearest: None,
+                moved: None,
+            }
+        );
+    }
+
+    #[test]
+    fn relocate_follows_lines_into_copies() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        fs::write(repo_dir.join("source.txt"), "a\nb\nc\nd\ne\n").unwrap();
+        let oid = add_files(&repo, vec!["source.txt"]).unwrap();
+        let first = commit(&repo, oid, "commit 1").unwrap().to_string();
+
+        fs::write(repo_dir.join("source.txt"), "a\nb\nd\ne\n").unwrap();
+        fs::write(repo_dir.join("copy.txt"), "x\na\nb\nc\nd\ne\n").unwrap();
+        let oid = add_files(&repo, vec!["source.txt", "copy.txt"]).unwrap();
+        let second = commit(&repo, oid, "commit 2").unwrap().to_string();
+
+        let git = Git::new().with_similarity(SimilarityOptions {
+            renames: true,
+            copies: true,
+            threshold: 50,
+        });
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let location = Location {
+            path: RelativePathBuf::from("source.txt".to_owned()),
+            line: 3,
+        };
+        let relocated = Relocator::new(&git, Mode::Direct)
+            .relocate(
+                repo_path.as_absolute_path(),
+                location,
+                None,
+                &first,
+                &second,
+            )
+            .unwrap();
+        assert_eq!(
+            relocated,
+            Relocation::Found(Location {
+                path: RelativePathBuf::from("copy.txt".to_owned()),
+                line: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn relocate_reports_commit_that_deleted_line() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+        let file = repo_dir.join("file.txt");
+
+        fs::write(&file, "1\n2\n3\ntarget\n5\n6\n7\n").unwrap();
+        let oid = add_files(&repo, vec!["file.txt"]).unwrap();
+        let first = commit(&repo, oid, "commit 1").unwrap().to_string();
+
+        fs::write(&file, "1\n2\n3\n5\n6\n7\n").unwrap();
+        let oid = add_files(&repo, vec!["file.txt"]).unwrap();
+        let second = commit(&repo, oid, "commit 2").unwrap().to_string();
+
+        fs::write(&file, "0\n1\n2\n3\ntarget\n5\n6\n7\n").unwrap();
+        let oid = add_files(&repo, vec!["file.txt"]).unwrap();
+        let third = commit(&repo, oid, "commit 3").unwrap().to_string();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        assert_eq!(
+            git.first_parent_history(repo_path.as_absolute_path(), &first, "HEAD")
+                .unwrap(),
+            vec![first.clone(), second.clone(), third.clone()]
+        );
+
+        let location = Location {
+            path: RelativePathBuf::from("file.txt".to_owned()),
+            line: 4,
+        };
+        let direct = Relocator::new(&git, Mode::Direct)
+            .relocate(
+                repo_path.as_absolute_path(),
+                location.clone(),
+                None,
+                &first,
+                &third,
+            )
+            .unwrap();
+        assert_eq!(
+            direct,
+            Relocation::Found(Location {
+                path: location.path.clone(),
+                line: 5,
+            })
+        );
+
+        let walked = Relocator::new(&git, Mode::History)
+            .relocate(repo_path.as_absolute_path(), location, None, &first, &third)
+            .unwrap();
+        match walked {
+            Relocation::Deleted { commit, nearest } => {
+                assert_eq!(commit, second);
+                assert!(nearest.is_some());
+            }
+            other => panic!("expected a deletion, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn recover_rebased_commit() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+        let a = repo_dir.join("a.txt");
+
+        fs::write(&a, "1\n2\n3\n").unwrap();
+        let oid = add_files(&repo, vec!["a.txt"]).unwrap();
+        let base = commit(&repo, oid, "base").unwrap();
+
+        fs::write(&a, "1\ntwo\n3\n").unwrap();
+        let oid = add_files(&repo, vec!["a.txt"]).unwrap();
+        let original = commit(&repo, oid, "change").unwrap().to_string();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let path = RelativePathBuf::from("a.txt".to_owned());
+        let fingerprint = git
+            .fingerprint(repo_path.as_absolute_path(), &original)
+            .unwrap();
+        let blob = git
+            .blob_id(repo_path.as_absolute_path(), &original, &path)
+            .unwrap();
+
+        let base = repo.find_object(base, None).unwrap();
+        repo.reset(&base, ResetType::Hard, None).unwrap();
+        fs::write(repo_dir.join("b.txt"), "upstream").unwrap();
+        let oid = add_files(&repo, vec!["b.txt"]).unwrap();
+        commit(&repo, oid, "upstream").unwrap();
+        fs::write(&a, "1\ntwo\n3\n").unwrap();
+        let oid = add_files(&repo, vec!["a.txt"]).unwrap();
+        let rebased = commit(&repo, oid, "change").unwrap().to_string();
+
+        let missing = "0123456789abcdef0123456789abcdef01234567";
+        assert!(!git
+            .commit_exists(repo_path.as_absolute_path(), missing)
+            .unwrap());
+        match git.lookup_file_bytes(repo_path.as_absolute_path(), missing, &path) {
+            Err(Error::CommitNotFound(c)) => assert_eq!(c, missing),
+            _ => panic!("expected CommitNotFound"),
+        }
+
+        assert_eq!(
+            git.find_replacement(
+                repo_path.as_absolute_path(),
+                Some(&fingerprint),
+                &[(path.clone(), blob.clone())]
+            )
+            .unwrap(),
+            Some(Replacement {
+                commit: rebased.clone(),
+                reason: ReplacementReason::Patch,
+            })
+        );
+        assert_eq!(
+            git.find_replacement(repo_path.as_absolute_path(), None, &[(path, blob)])
+                .unwrap(),
+            Some(Replacement {
+                commit: rebased,
+                reason: ReplacementReason::StopFiles,
+            })
+        );
+    }
+
+    #[test]
+    fn create_and_delete_refs() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+        fs::write(repo_dir.join("a.txt"), "a").unwrap();
+        let oid = add_files(&repo, vec!["a.txt"]).unwrap();
+        let id = commit(&repo, oid, "commit 1").unwrap();
+
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        for name in &["refs/tourist/tour/a", "refs/tourist/tour/b"] {
+            git.create_ref(repo_path.as_absolute_path(), name, &id.to_string())
+                .unwrap();
+        }
+        git.create_ref(
+            repo_path.as_absolute_path(),
+            "refs/tourist/other/a",
+            &id.to_string(),
+        )
+        .unwrap();
+        assert_eq!(repo.refname_to_id("refs/tourist/tour/a").unwrap(), id);
+        assert_eq!(
+            git.delete_refs(repo_path.as_absolute_path(), "refs/tourist/tour")
+                .unwrap(),
+            2
+        );
+        assert!(repo.find_reference("refs/tourist/tour/a").is_err());
+        assert!(repo.find_reference("refs/tourist/other/a").is_ok());
+    }
+
+    #[test]
+    fn diff_with_worktree_sees_uncommitted_edits() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+        fs::write(repo_dir.join("a.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+        fs::write(repo_dir.join("b.txt"), "one\ntwo\nthree\n").unwrap();
+        let oid = add_files(&repo, vec!["a.txt", "b.txt"]).unwrap();
+        let id = commit(&repo, oid, "commit 1").unwrap();
+
+        fs::write(repo_dir.join("a.txt"), "zero\none\nTWO\nfour\n").unwrap();
+        fs::write(repo_dir.join("b.txt"), "one\nthree\n").unwrap();
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let changes = git
+            .diff_with_worktree(repo_path.as_absolute_path(), &id.to_string())
+            .unwrap();
+        let path = RelativePathBuf::from("a.txt".to_owned());
+        assert_eq!(changes.map_line(&path, 1), LineFate::Moved { line: 2 });
+        assert!(changes.is_edited(&path, 2));
+        assert_eq!(changes.map_line(&path, 4), LineFate::Unchanged);
+        assert!(!changes.is_edited(&RelativePathBuf::from("b.txt".to_owned()), 2));
+    }
+
+    #[test]
+    fn diff_with_index_ignores_unstaged_edits() {
+        let repo_dir = TempDir::new("my_repo").unwrap().into_path();
+        let repo = Repository::init(&repo_dir).unwrap();
+        fs::write(repo_dir.join("a.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+        let oid = add_files(&repo, vec!["a.txt"]).unwrap();
+        let id = commit(&repo, oid, "commit 1").unwrap();
+
+        fs::write(repo_dir.join("a.txt"), "one\nthree\nfour\n").unwrap();
+        add_files(&repo, vec!["a.txt"]).unwrap();
+        fs::write(repo_dir.join("a.txt"), "one\nthree\n").unwrap();
+        let git = Git::new();
+        let repo_path = AbsolutePathBuf::new(repo_dir.clone()).unwrap();
+        let path = RelativePathBuf::from("a.txt".to_owned());
+        let staged = git
+            .diff_with_index(repo_path.as_absolute_path(), &id.to_string())
+            .unwrap();
+        let deleted = |fate| match fate {
+            LineFate::Deleted { .. } => true,
+            _ => false,
+        };
+        assert!(deleted(staged.map_line(&path, 2)));
+        assert_eq!(staged.map_line(&path, 4), LineFate::Moved { line: 3 });
+        let all = git
+            .diff_with_worktree(repo_path.as_absolute_path(), &id.to_string())
+            .unwrap();
+        assert!(deleted(all.map_line(&path, 4)));
+    }
 }
@@ -0,0 +1,111 @@
+use crate::error::Result;
+use crate::types::path::RelativePathBuf;
+use crate::types::CommitFingerprint;
+use git2::{Commit, ObjectType, Oid, Repository};
+use std::path::Path;
+
+/// How many commits reachable from the repository's refs are searched for a replacement.
+const SEARCH_LIMIT: usize = 2000;
+
+/// Why a commit was proposed as the replacement for one that no longer exists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplacementReason {
+    Tree,
+    Patch,
+    StopFiles,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Replacement {
+    pub commit: String,
+    pub reason: ReplacementReason,
+}
+
+pub fn fingerprint(repo: &Repository, commit: &Commit<'_>) -> Result<CommitFingerprint> {
+    Ok(CommitFingerprint {
+        tree: commit.tree_id().to_string(),
+        patch: patch_id(repo, commit)?.to_string(),
+    })
+}
+
+/// A hash of the commit's changes against its first parent, ignoring whitespace and line
+/// numbers, so a rebased commit keeps the id of the original. Like `git patch-id` in spirit,
+/// though the ids differ.
+fn patch_id(repo: &Repository, commit: &Commit<'_>) -> Result<Oid> {
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    let mut buffer = vec![];
+    diff.print(git2::DiffFormat::Patch, |delta, _, line| {
+        match line.origin() {
+            '+' | '-' => {
+                buffer.push(line.origin() as u8);
+                buffer.extend(line.content().iter().filter(|b| !b.is_ascii_whitespace()));
+            }
+            'F' => {
+                for file in &[delta.old_file(), delta.new_file()] {
+                    if let Some(path) = file.path().and_then(Path::to_str) {
+                        buffer.extend(path.as_bytes());
+                    }
+                    buffer.push(0);
+                }
+            }
+            _ => {}
+        }
+        true
+    })?;
+    Ok(Oid::hash_object(ObjectType::Blob, &buffer)?)
+}
+
+/// Searches history reachable from any ref for the best stand-in for a missing commit: one with
+/// the same tree, then one with the same patch, then one where every stop file has the blob it
+/// had at the missing commit. Newer commits are preferred within each kind.
+pub fn find_replacement(
+    repo: &Repository,
+    fingerprint: Option<&CommitFingerprint>,
+    blobs: &[(RelativePathBuf, String)],
+) -> Result<Option<Replacement>> {
+    let mut walk = repo.revwalk()?;
+    walk.push_glob("refs/*")?;
+
+    let mut by_patch = None;
+    let mut by_blobs = None;
+    for id in walk.take(SEARCH_LIMIT) {
+        let commit = repo.find_commit(id?)?;
+        if let Some(fingerprint) = fingerprint {
+            if commit.tree_id().to_string() == fingerprint.tree {
+                return Ok(Some(Replacement {
+                    commit: commit.id().to_string(),
+                    reason: ReplacementReason::Tree,
+                }));
+            }
+            if by_patch.is_none() && patch_id(repo, &commit)?.to_string() == fingerprint.patch {
+                by_patch = Some(commit.id());
+            }
+        }
+        if by_blobs.is_none() && !blobs.is_empty() && has_blobs(&commit, blobs)? {
+            by_blobs = Some(commit.id());
+        }
+    }
+
+    Ok(by_patch
+        .map(|id| (id, ReplacementReason::Patch))
+        .or_else(|| by_blobs.map(|id| (id, ReplacementReason::StopFiles)))
+        .map(|(id, reason)| Replacement {
+            commit: id.to_string(),
+            reason,
+        }))
+}
+
+fn has_blobs(commit: &Commit<'_>, blobs: &[(RelativePathBuf, String)]) -> Result<bool> {
+    let tree = commit.tree()?;
+    for (path, blob) in blobs {
+        match tree.get_path(Path::new(&path.as_git_path())) {
+            Ok(entry) if entry.id().to_string() == *blob => {}
+            _ => return Ok(false),
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,38 @@
+//! Builders for the git repositories tests run against.
+
+use git2::{Commit, ObjectType, Oid, Repository, Signature};
+use std::path::Path;
+
+fn find_last_commit(repo: &Repository) -> Result<Commit, git2::Error> {
+    let obj = repo.head()?.resolve()?.peel(ObjectType::Commit)?;
+    obj.into_commit()
+        .map_err(|_| git2::Error::from_str("Couldn't find commit"))
+}
+
+/// Stages `files` and returns the id of the tree the index now holds.
+pub fn add_files<P: AsRef<Path>>(repo: &Repository, files: Vec<P>) -> Result<Oid, git2::Error> {
+    let mut index = repo.index()?;
+    for file in files {
+        index.add_path(file.as_ref())?;
+    }
+    index.write()?;
+    index.write_tree()
+}
+
+/// Commits the tree `oid` on top of HEAD, or as the first commit if there is none yet.
+pub fn commit(repo: &Repository, oid: Oid, message: &str) -> Result<Oid, git2::Error> {
+    let signature = Signature::now("Test User", "test@user.net")?;
+    let tree = repo.find_tree(oid)?;
+    let parent = match find_last_commit(&repo) {
+        Ok(p) => vec![p],
+        Err(_) => vec![],
+    };
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parent.iter().map(|x| &*x).collect::<Vec<_>>(),
+    )
+}
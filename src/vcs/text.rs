@@ -0,0 +1,126 @@
+/// How far into a file to look for a NUL byte when deciding whether it is binary, as git does.
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_LENGTH)].contains(&0)
+}
+
+/// Guesses whether `content`, which has no byte order mark, is UTF-16 from where its NUL bytes
+/// fall: mostly-ASCII UTF-16 has a NUL in the high byte of most code units and nowhere else.
+fn sniff_utf16(content: &[u8]) -> Option<fn([u8; 2]) -> u16> {
+    let sample = &content[..content.len().min(BINARY_CHECK_LENGTH)];
+    if content.len() % 2 != 0 || sample.len() < 2 {
+        return None;
+    }
+    let units = sample.len() / 2;
+    let nuls = |offset: usize| {
+        sample
+            .chunks_exact(2)
+            .filter(|pair| pair[offset] == 0)
+            .count()
+    };
+    let (even, odd) = (nuls(0), nuls(1));
+    let from_bytes: fn([u8; 2]) -> u16 = if odd * 2 >= units && even * 10 <= odd {
+        u16::from_le_bytes
+    } else if even * 2 >= units && odd * 10 <= even {
+        u16::from_be_bytes
+    } else {
+        return None;
+    };
+    let units = content
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    match String::from_utf16(&units) {
+        Ok(text) if !text.contains('\0') => Some(from_bytes),
+        _ => None,
+    }
+}
+
+/// Decodes file contents to text with `\n` line endings. A byte order mark selects UTF-8 or
+/// UTF-16, and UTF-16 without one is recognized by its pattern of NUL bytes; otherwise the
+/// contents are read as UTF-8, falling back to Latin-1. Returns `None` for binary contents.
+pub fn decode(content: &[u8]) -> Option<String> {
+    let text = if content.starts_with(UTF8_BOM) {
+        String::from_utf8_lossy(&content[UTF8_BOM.len()..]).into_owned()
+    } else if content.starts_with(UTF16_LE_BOM) {
+        decode_utf16(&content[UTF16_LE_BOM.len()..], u16::from_le_bytes)
+    } else if content.starts_with(UTF16_BE_BOM) {
+        decode_utf16(&content[UTF16_BE_BOM.len()..], u16::from_be_bytes)
+    } else if is_binary(content) {
+        decode_utf16(content, sniff_utf16(content)?)
+    } else {
+        match std::str::from_utf8(content) {
+            Ok(s) => s.to_owned(),
+            Err(_) => content.iter().map(|&b| char::from(b)).collect(),
+        }
+    };
+    Some(normalize_line_endings(&text))
+}
+
+fn decode_utf16(content: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = content
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+pub fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Replaces tabs with spaces up to the next multiple of `width` columns.
+pub fn expand_tabs(text: &str, width: usize) -> String {
+    if width == 0 {
+        return text.to_owned();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = width - column % width;
+                (0..spaces).for_each(|_| out.push(' '));
+                column += spaces;
+            }
+            '\n' => {
+                out.push(c);
+                column = 0;
+            }
+            _ => {
+                out.push(c);
+                column += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, expand_tabs};
+
+    #[test]
+    fn decode_detects_encodings() {
+        assert_eq!(decode(b"plain\r\ntext\r"), Some("plain\ntext\n".to_owned()));
+        assert_eq!(decode(b"\xEF\xBB\xBFbom"), Some("bom".to_owned()));
+        assert_eq!(decode(b"\xFF\xFEh\0i\0"), Some("hi".to_owned()));
+        assert_eq!(decode(b"\xFE\xFF\0h\0i"), Some("hi".to_owned()));
+        assert_eq!(decode(b"caf\xE9"), Some("caf\u{e9}".to_owned()));
+        assert_eq!(decode(b"\0\x01\x02"), None);
+        assert_eq!(decode(b"h\0i\0\r\0\n\0"), Some("hi\n".to_owned()));
+        assert_eq!(decode(b"\0h\0i"), Some("hi".to_owned()));
+        assert_eq!(decode(b"\0\0\x01\0\0\0\x02\0"), None);
+    }
+
+    #[test]
+    fn expand_tabs_aligns_to_stops() {
+        assert_eq!(expand_tabs("\tx\nab\tc", 4), "    x\nab  c");
+        assert_eq!(expand_tabs("\tx", 0), "\tx");
+    }
+}
@@ -0,0 +1,159 @@
+use crate::config::{Config, Layer};
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file name of a workspace manifest, looked for in the starting directory and its ancestors.
+pub const MANIFEST: &str = "tourist.workspace.json";
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct WorkspaceRepository {
+    /// Where the repository lives, relative to the workspace root.
+    pub path: PathBuf,
+    /// A local path or `file://` URL to clone the repository from if it is missing.
+    #[serde(default)]
+    pub mirror: Option<String>,
+}
+
+/// A manifest shared by everyone working on a set of tours, mapping repository names to
+/// checkouts relative to the workspace root.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Workspace {
+    #[serde(skip)]
+    pub root: PathBuf,
+    pub repositories: BTreeMap<String, WorkspaceRepository>,
+}
+
+impl Workspace {
+    pub fn parse(source: &str, root: &Path) -> Result<Self> {
+        let mut workspace: Workspace = serde_json::from_str(source)?;
+        workspace.root = root.to_owned();
+        Ok(workspace)
+    }
+
+    /// Finds and reads the manifest nearest to `start`.
+    pub fn find(start: &Path) -> Result<Option<(PathBuf, Workspace)>> {
+        let path = match start
+            .ancestors()
+            .map(|dir| dir.join(MANIFEST))
+            .find(|path| path.exists())
+        {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let root = path.parent().unwrap_or(start);
+        let workspace = Workspace::parse(&fs::read_to_string(&path)?, root)?;
+        Ok(Some((path, workspace)))
+    }
+
+    pub fn repository_path(&self, repository: &WorkspaceRepository) -> PathBuf {
+        self.root.join(&repository.path)
+    }
+
+    /// Where to clone the repository `name` from: its mirror, with a relative path resolved
+    /// against the workspace root as `path` is. Anything other than a path or a `file://` URL is
+    /// refused, since the manifest is shared and should not make `sync` reach out to the network.
+    pub fn mirror_source(
+        &self,
+        name: &str,
+        repository: &WorkspaceRepository,
+    ) -> Result<Option<String>> {
+        let mirror = match &repository.mirror {
+            Some(mirror) => mirror,
+            None => return Ok(None),
+        };
+        if mirror.starts_with("file://") {
+            return Ok(Some(mirror.clone()));
+        }
+        // `scheme://...` and scp-style `host:path` are remote; a one-letter prefix is a drive.
+        let remote = mirror.contains("://")
+            || mirror
+                .find(':')
+                .map_or(false, |i| i > 1 && !mirror[..i].contains('/'));
+        if remote {
+            return Err(Error::UnsupportedMirror(name.to_owned(), mirror.clone()));
+        }
+        Ok(Some(self.root.join(mirror).to_string_lossy().into_owned()))
+    }
+
+    /// The workspace as a config layer holding only an index.
+    pub fn into_layer(self, path: PathBuf) -> Layer {
+        let index = self
+            .repositories
+            .iter()
+            .map(|(name, repository)| (name.clone(), self.repository_path(repository)))
+            .collect();
+        Layer {
+            path,
+            config: Config {
+                index,
+                ..Config::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Workspace, MANIFEST};
+    use crate::error::Error;
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    #[test]
+    fn resolves_paths_against_the_root() {
+        let root = TempDir::new("workspace").unwrap().into_path();
+        fs::create_dir_all(root.join("app").join("docs")).unwrap();
+        fs::write(
+            root.join(MANIFEST),
+            r#"{ "repositories": {
+                "app": { "path": "app" },
+                "lib": { "path": "deps/lib", "mirror": "file:///srv/git/lib.git" }
+            } }"#,
+        )
+        .unwrap();
+        let (path, workspace) = Workspace::find(&root.join("app").join("docs"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, root.join(MANIFEST));
+        let layer = workspace.into_layer(path);
+        assert_eq!(layer.config.index["app"], root.join("app"));
+        assert_eq!(layer.config.index["lib"], root.join("deps/lib"));
+    }
+
+    #[test]
+    fn resolves_mirrors_against_the_root() {
+        let root = Path::new("/work");
+        let workspace = Workspace::parse(
+            r#"{ "repositories": {
+                "a": { "path": "a", "mirror": "../mirrors/a.git" },
+                "b": { "path": "b", "mirror": "file:///srv/git/b.git" },
+                "c": { "path": "c", "mirror": "https://example.com/c.git" },
+                "d": { "path": "d", "mirror": "git@example.com:d.git" },
+                "e": { "path": "e" }
+            } }"#,
+            root,
+        )
+        .unwrap();
+        let source = |name: &str| workspace.mirror_source(name, &workspace.repositories[name]);
+        assert_eq!(
+            source("a").unwrap(),
+            Some(root.join("../mirrors/a.git").to_string_lossy().into_owned())
+        );
+        assert_eq!(
+            source("b").unwrap(),
+            Some("file:///srv/git/b.git".to_owned())
+        );
+        match source("c") {
+            Err(Error::UnsupportedMirror(name, _)) => assert_eq!(name, "c"),
+            other => panic!("expected an unsupported mirror, got {:?}", other),
+        }
+        assert!(source("d").is_err());
+        assert_eq!(source("e").unwrap(), None);
+    }
+}